@@ -0,0 +1,487 @@
+use anyhow::ensure;
+use anyhow::Context;
+use rusqlite::OptionalExtension;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// The current version of the archive schema.
+const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS posts (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT,
+    username TEXT NOT NULL,
+    nsfw INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    metadata TEXT NOT NULL,
+    downloaded_at INTEGER NOT NULL
+) STRICT;
+
+CREATE TABLE IF NOT EXISTS files (
+    post_id TEXT NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    link TEXT NOT NULL,
+    description TEXT,
+    path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    downloaded_at INTEGER NOT NULL,
+    PRIMARY KEY (post_id, id)
+) STRICT;
+
+CREATE INDEX IF NOT EXISTS posts_username ON posts (username);
+";
+
+/// A local database of downloaded posts.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    connection: Arc<Mutex<rusqlite::Connection>>,
+}
+
+/// A post to add to the archive.
+#[derive(Debug)]
+pub struct NewPost {
+    pub id: String,
+    pub title: Option<String>,
+    pub username: String,
+    pub nsfw: bool,
+
+    /// The url of the post.
+    pub source: String,
+
+    /// The directory the post was downloaded to.
+    pub directory: String,
+
+    /// The post, as json.
+    ///
+    /// Its "kind" key is "api" or "scraped", depending on where the post came from.
+    pub metadata: String,
+
+    pub files: Vec<NewFile>,
+}
+
+/// A downloaded file to add to the archive.
+#[derive(Debug)]
+pub struct NewFile {
+    pub id: String,
+    pub position: u32,
+    pub link: String,
+    pub description: Option<String>,
+
+    /// The path of the file, relative to the post directory.
+    pub path: String,
+
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// An archived post.
+#[derive(Debug, serde::Serialize)]
+pub struct ArchivedPost {
+    pub id: String,
+    pub title: Option<String>,
+    pub username: String,
+    pub nsfw: bool,
+    pub source: String,
+    pub directory: String,
+    pub file_count: u64,
+
+    /// When the post was last downloaded, in UTC.
+    pub downloaded_at: String,
+}
+
+/// An archived file.
+#[derive(Debug)]
+pub struct ArchivedFile {
+    pub post_id: String,
+
+    /// The full path of the file.
+    pub path: PathBuf,
+
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Statistics about an archive.
+#[derive(Debug, serde::Serialize)]
+pub struct ArchiveStats {
+    pub posts: u64,
+    pub files: u64,
+    pub users: u64,
+
+    /// The total size of all files, in bytes.
+    pub total_size: u64,
+}
+
+impl Archive {
+    /// Open an archive, creating it if it does not exist.
+    pub async fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let connection = tokio::task::spawn_blocking(move || {
+            let connection = rusqlite::Connection::open(&path)
+                .with_context(|| format!("failed to open \"{}\"", path.display()))?;
+            connection.pragma_update(None, "foreign_keys", true)?;
+            connection.busy_timeout(std::time::Duration::from_secs(5))?;
+
+            let version: u32 =
+                connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+            ensure!(
+                version <= SCHEMA_VERSION,
+                "archive version {version} is newer than the supported version {SCHEMA_VERSION}"
+            );
+            connection.execute_batch(SCHEMA)?;
+            connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+            anyhow::Ok(connection)
+        })
+        .await??;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Run a function with the connection on a blocking thread.
+    async fn with_connection<F, T>(&self, func: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut rusqlite::Connection) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().unwrap_or_else(|error| error.into_inner());
+            func(&mut connection)
+        })
+        .await?
+    }
+
+    /// Check if a post is in the archive.
+    pub async fn contains_post(&self, id: &str) -> anyhow::Result<bool> {
+        let id = id.to_string();
+        self.with_connection(move |connection| {
+            let exists = connection
+                .query_row("SELECT 1 FROM posts WHERE id = ?1", [&id], |_row| Ok(()))
+                .optional()?
+                .is_some();
+            Ok(exists)
+        })
+        .await
+    }
+
+    /// Add a post and its files to the archive, replacing any old data.
+    ///
+    /// Files keep their old download time if their checksum did not change.
+    /// Old files that are not in the new post are removed.
+    pub async fn add_post(&self, post: NewPost) -> anyhow::Result<()> {
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "
+                INSERT INTO posts (id, title, username, nsfw, source, directory, metadata, downloaded_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, unixepoch())
+                ON CONFLICT (id) DO UPDATE SET
+                    title = excluded.title,
+                    username = excluded.username,
+                    nsfw = excluded.nsfw,
+                    source = excluded.source,
+                    directory = excluded.directory,
+                    metadata = excluded.metadata,
+                    downloaded_at = excluded.downloaded_at
+                ",
+                rusqlite::params![
+                    post.id,
+                    post.title,
+                    post.username,
+                    post.nsfw,
+                    post.source,
+                    post.directory,
+                    post.metadata,
+                ],
+            )?;
+
+            {
+                let mut statement = transaction.prepare(
+                    "
+                    INSERT INTO files (post_id, id, position, link, description, path, size, sha256, downloaded_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, unixepoch())
+                    ON CONFLICT (post_id, id) DO UPDATE SET
+                        position = excluded.position,
+                        link = excluded.link,
+                        description = excluded.description,
+                        path = excluded.path,
+                        size = excluded.size,
+                        sha256 = excluded.sha256,
+                        downloaded_at = CASE
+                            WHEN files.sha256 = excluded.sha256 THEN files.downloaded_at
+                            ELSE excluded.downloaded_at
+                        END
+                    ",
+                )?;
+                for file in post.files.iter() {
+                    statement.execute(rusqlite::params![
+                        post.id,
+                        file.id,
+                        file.position,
+                        file.link,
+                        file.description,
+                        file.path,
+                        file.size,
+                        file.sha256,
+                    ])?;
+                }
+            }
+
+            let file_ids: Vec<&str> = post.files.iter().map(|file| file.id.as_str()).collect();
+            transaction.execute(
+                "
+                DELETE FROM files
+                WHERE post_id = ?1 AND id NOT IN (SELECT value FROM json_each(?2))
+                ",
+                rusqlite::params![post.id, serde_json::to_string(&file_ids)?],
+            )?;
+
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// List archived posts, newest first.
+    ///
+    /// If a query is given, only posts whose title, username, or descriptions contain it are listed.
+    /// Api posts have no post description, so only their file descriptions are searched.
+    pub async fn list_posts(
+        &self,
+        username: Option<String>,
+        query: Option<String>,
+    ) -> anyhow::Result<Vec<ArchivedPost>> {
+        let pattern = query.map(|query| format!("%{}%", escape_like(&query)));
+        self.with_connection(move |connection| {
+            let mut statement = connection.prepare(
+                "
+                SELECT
+                    posts.id,
+                    posts.title,
+                    posts.username,
+                    posts.nsfw,
+                    posts.source,
+                    posts.directory,
+                    (SELECT COUNT(*) FROM files WHERE files.post_id = posts.id),
+                    datetime(posts.downloaded_at, 'unixepoch')
+                FROM posts
+                WHERE
+                    (?1 IS NULL OR posts.username = ?1)
+                    AND (
+                        ?2 IS NULL
+                        OR posts.title LIKE ?2 ESCAPE '\\'
+                        OR posts.username LIKE ?2 ESCAPE '\\'
+                        OR json_extract(posts.metadata, '$.description') LIKE ?2 ESCAPE '\\'
+                        OR EXISTS (
+                            SELECT 1 FROM files
+                            WHERE files.post_id = posts.id AND files.description LIKE ?2 ESCAPE '\\'
+                        )
+                    )
+                ORDER BY posts.downloaded_at DESC, posts.id
+                ",
+            )?;
+            let posts = statement
+                .query_map(rusqlite::params![username, pattern], |row| {
+                    Ok(ArchivedPost {
+                        id: row.get(0)?,
+                        title: row.get(1)?,
+                        username: row.get(2)?,
+                        nsfw: row.get(3)?,
+                        source: row.get(4)?,
+                        directory: row.get(5)?,
+                        file_count: row.get(6)?,
+                        downloaded_at: row.get(7)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(posts)
+        })
+        .await
+    }
+
+    /// Get statistics about the archive.
+    pub async fn stats(&self) -> anyhow::Result<ArchiveStats> {
+        self.with_connection(|connection| {
+            let stats = connection.query_row(
+                "
+                SELECT
+                    (SELECT COUNT(*) FROM posts),
+                    (SELECT COUNT(*) FROM files),
+                    (SELECT COUNT(DISTINCT username) FROM posts),
+                    (SELECT COALESCE(SUM(size), 0) FROM files)
+                ",
+                [],
+                |row| {
+                    Ok(ArchiveStats {
+                        posts: row.get(0)?,
+                        files: row.get(1)?,
+                        users: row.get(2)?,
+                        total_size: row.get(3)?,
+                    })
+                },
+            )?;
+
+            Ok(stats)
+        })
+        .await
+    }
+
+    /// Get all archived files.
+    pub async fn files(&self) -> anyhow::Result<Vec<ArchivedFile>> {
+        self.with_connection(|connection| {
+            let mut statement = connection.prepare(
+                "
+                SELECT files.post_id, posts.directory, files.path, files.size, files.sha256
+                FROM files
+                JOIN posts ON posts.id = files.post_id
+                ORDER BY files.post_id, files.position
+                ",
+            )?;
+            let files = statement
+                .query_map([], |row| {
+                    let directory: String = row.get(1)?;
+                    let path: String = row.get(2)?;
+                    Ok(ArchivedFile {
+                        post_id: row.get(0)?,
+                        path: PathBuf::from(directory).join(path),
+                        size: row.get(3)?,
+                        sha256: row.get(4)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(files)
+        })
+        .await
+    }
+}
+
+/// Escape the wildcards of a LIKE pattern.
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    fn new_post(id: &str, title: &str, file_ids: &[&str]) -> NewPost {
+        NewPost {
+            id: id.into(),
+            title: Some(title.into()),
+            username: "LunarLandr".into(),
+            nsfw: false,
+            source: format!("https://imgchest.com/p/{id}"),
+            directory: format!("/downloads/{id}"),
+            metadata: "{}".into(),
+            files: file_ids
+                .iter()
+                .zip(1..)
+                .map(|(file_id, position)| NewFile {
+                    id: file_id.to_string(),
+                    position,
+                    link: format!("https://cdn.imgchest.com/files/{file_id}.png"),
+                    description: None,
+                    path: format!("{file_id}.png"),
+                    size: 16,
+                    sha256: "00".into(),
+                })
+                .collect(),
+        }
+    }
+
+    async fn open_memory() -> Archive {
+        Archive::open(":memory:".into())
+            .await
+            .expect("failed to open archive")
+    }
+
+    #[tokio::test]
+    async fn add_post_twice() {
+        let archive = open_memory().await;
+        for _ in 0..2 {
+            archive
+                .add_post(new_post("3qe4gdvj4j2", "Donkey Kong", &["a", "b"]))
+                .await
+                .expect("failed to add post");
+        }
+
+        let stats = archive.stats().await.expect("failed to get stats");
+        assert!(stats.posts == 1);
+        assert!(stats.files == 2);
+        assert!(stats.total_size == 32);
+        assert!(archive
+            .contains_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to check post"));
+    }
+
+    #[tokio::test]
+    async fn add_post_removes_old_files() {
+        let archive = open_memory().await;
+        archive
+            .add_post(new_post("3qe4gdvj4j2", "Donkey Kong", &["a", "b", "c"]))
+            .await
+            .expect("failed to add post");
+        archive
+            .add_post(new_post("3qe4gdvj4j2", "Donkey Kong", &["b"]))
+            .await
+            .expect("failed to add post");
+
+        let files = archive.files().await.expect("failed to get files");
+        assert!(files.len() == 1);
+        assert!(files[0].path == Path::new("/downloads/3qe4gdvj4j2/b.png"));
+    }
+
+    #[tokio::test]
+    async fn list_posts_escapes_query() {
+        let archive = open_memory().await;
+        for (id, title) in [
+            ("aaaaaaaaaaa", "100% done"),
+            ("bbbbbbbbbbb", "100 done"),
+            ("ccccccccccc", "snake_case"),
+            ("ddddddddddd", "snakeXcase"),
+        ] {
+            archive
+                .add_post(new_post(id, title, &["a"]))
+                .await
+                .expect("failed to add post");
+        }
+
+        let search = |query: &str| {
+            let archive = archive.clone();
+            let query = query.to_string();
+            async move {
+                let posts = archive
+                    .list_posts(None, Some(query))
+                    .await
+                    .expect("failed to list posts");
+                posts.into_iter().map(|post| post.id).collect::<Vec<_>>()
+            }
+        };
+        assert!(search("100%").await == ["aaaaaaaaaaa"]);
+        assert!(search("e_c").await == ["ccccccccccc"]);
+        assert!(search("done").await.len() == 2);
+    }
+
+    #[test]
+    fn escape_like_wildcards() {
+        assert!(escape_like(r"50%_a\b") == r"50\%\_a\\b");
+    }
+}
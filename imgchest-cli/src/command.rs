@@ -1,4 +1,7 @@
+pub mod archive;
 pub mod config;
 pub mod download;
 pub mod list_posts;
+pub mod post;
 pub mod profile;
+pub mod upload;
@@ -0,0 +1,201 @@
+use crate::archive::Archive;
+use crate::archive::ArchivedPost;
+use crate::command::download::manifest::ManifestEntry;
+use crate::command::list_posts::OutputFormat;
+use anyhow::bail;
+use std::path::PathBuf;
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "archive",
+    description = "query an archive of downloaded posts"
+)]
+pub struct Options {
+    #[argh(
+        option,
+        short = 'a',
+        long = "archive",
+        description = "the archive database, as passed to the download command"
+    )]
+    archive: PathBuf,
+
+    #[argh(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand)]
+enum Subcommand {
+    List(ListOptions),
+    Search(SearchOptions),
+    Stats(StatsOptions),
+    Verify(VerifyOptions),
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "list", description = "list archived posts")]
+pub struct ListOptions {
+    #[argh(
+        option,
+        long = "username",
+        description = "only list posts by this user"
+    )]
+    pub username: Option<String>,
+
+    #[argh(
+        option,
+        long = "output-format",
+        default = "Default::default()",
+        description = "the output format"
+    )]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "search",
+    description = "search archived posts by title, username, and descriptions"
+)]
+pub struct SearchOptions {
+    #[argh(positional, description = "the text to search for")]
+    pub query: String,
+
+    #[argh(
+        option,
+        long = "output-format",
+        default = "Default::default()",
+        description = "the output format"
+    )]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "stats", description = "show archive statistics")]
+pub struct StatsOptions {
+    #[argh(
+        option,
+        long = "output-format",
+        default = "Default::default()",
+        description = "the output format"
+    )]
+    pub output_format: OutputFormat,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "verify",
+    description = "check that archived files exist and are unchanged"
+)]
+pub struct VerifyOptions {
+    #[argh(
+        switch,
+        long = "checksums",
+        description = "also check file checksums, instead of only sizes"
+    )]
+    pub checksums: bool,
+}
+
+pub async fn exec(options: Options) -> anyhow::Result<()> {
+    if !tokio::fs::try_exists(&options.archive).await? {
+        bail!("archive \"{}\" does not exist", options.archive.display());
+    }
+    let archive = Archive::open(options.archive).await?;
+
+    match options.subcommand {
+        Subcommand::List(options) => {
+            let posts = archive.list_posts(options.username, None).await?;
+            output_posts(&posts, options.output_format)?;
+        }
+        Subcommand::Search(options) => {
+            let posts = archive.list_posts(None, Some(options.query)).await?;
+            output_posts(&posts, options.output_format)?;
+        }
+        Subcommand::Stats(options) => {
+            let stats = archive.stats().await?;
+            match options.output_format {
+                OutputFormat::Human => {
+                    println!("Posts: {}", stats.posts);
+                    println!("Files: {}", stats.files);
+                    println!("Users: {}", stats.users);
+                    println!("Total Size: {} bytes", stats.total_size);
+                }
+                OutputFormat::Json => {
+                    let stdout = std::io::stdout().lock();
+                    serde_json::to_writer(stdout, &stats)?;
+                }
+            }
+        }
+        Subcommand::Verify(options) => {
+            let files = archive.files().await?;
+            let mut missing = 0;
+            let mut changed = 0;
+            for file in files.iter() {
+                let metadata = match tokio::fs::metadata(&file.path).await {
+                    Ok(metadata) => metadata,
+                    Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        println!("Missing: {} ({})", file.path.display(), file.post_id);
+                        missing += 1;
+                        continue;
+                    }
+                    Err(error) => return Err(error.into()),
+                };
+
+                let is_valid = if options.checksums {
+                    let entry = ManifestEntry {
+                        size: file.size,
+                        sha256: file.sha256.clone(),
+                    };
+                    entry.verify(&file.path).await?
+                } else {
+                    metadata.len() == file.size
+                };
+                if !is_valid {
+                    println!("Changed: {} ({})", file.path.display(), file.post_id);
+                    changed += 1;
+                }
+            }
+
+            println!(
+                "Checked {} files, {missing} missing, {changed} changed",
+                files.len()
+            );
+            if missing + changed > 0 {
+                bail!("{} archived files failed verification", missing + changed);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn output_posts(posts: &[ArchivedPost], output_format: OutputFormat) -> anyhow::Result<()> {
+    match output_format {
+        OutputFormat::Human => {
+            for post in posts.iter() {
+                println!("Id: {}", post.id);
+                if let Some(title) = post.title.as_deref() {
+                    println!("Title: {title}");
+                }
+                println!("Username: {}", post.username);
+                println!("Nsfw: {}", post.nsfw);
+                println!("Files: {}", post.file_count);
+                println!("Directory: {}", post.directory);
+                println!("Downloaded: {}", post.downloaded_at);
+                println!();
+            }
+
+            if posts.is_empty() {
+                println!("No results");
+            }
+        }
+        OutputFormat::Json => {
+            let stdout = std::io::stdout().lock();
+            serde_json::to_writer(stdout, posts)?;
+        }
+    }
+
+    Ok(())
+}
@@ -1,19 +1,147 @@
+mod file;
+pub mod manifest;
+mod progress;
+mod sidecar;
+mod state;
+mod template;
+mod xmp;
+
+use self::file::download_file;
+use self::manifest::Manifest;
+use self::manifest::ManifestEntry;
+use self::manifest::MANIFEST_FILE_NAME;
+use self::progress::Progress;
+use self::sidecar::PostMetadata;
+use self::sidecar::DESCRIPTIONS_FILE_NAME;
+use self::state::DownloadState;
+use self::template::PathDeduplicator;
+use self::template::Template;
+use self::template::TemplateValues;
+use self::xmp::XmpMetadata;
+use crate::archive::Archive;
+use crate::archive::NewFile;
+use crate::archive::NewPost;
+use crate::command::list_posts::NsfwFilter;
+use crate::command::list_posts::SortOrder;
+use crate::command::list_posts::Timeframe;
+use anyhow::bail;
 use anyhow::ensure;
 use anyhow::Context;
+use futures_util::StreamExt;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
 use tokio::task::JoinSet;
 use url::Url;
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MediaKind {
+    Image,
+    Gif,
+    Video,
+    Unknown,
+}
+
+impl FromStr for MediaKind {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "image" => Ok(Self::Image),
+            "gif" => Ok(Self::Gif),
+            "video" => Ok(Self::Video),
+            "unknown" => Ok(Self::Unknown),
+            _ => bail!("unknown media kind \"{input}\""),
+        }
+    }
+}
+
+impl From<MediaKind> for imgchest::MediaKind {
+    fn from(kind: MediaKind) -> imgchest::MediaKind {
+        match kind {
+            MediaKind::Image => Self::Image,
+            MediaKind::Gif => Self::AnimatedGif,
+            MediaKind::Video => Self::Video,
+            MediaKind::Unknown => Self::Unknown,
+        }
+    }
+}
+
 #[derive(Debug, argh::FromArgs)]
 #[argh(
     subcommand,
     name = "download",
-    description = "download a post from imgchest.com"
+    description = "download posts from imgchest.com"
 )]
 pub struct Options {
-    #[argh(positional, description = "the url of the post")]
-    pub url: String,
+    #[argh(
+        positional,
+        description = "the urls or ids of posts, or the urls of users to download all posts from"
+    )]
+    pub targets: Vec<String>,
+
+    #[argh(
+        option,
+        long = "input-file",
+        short = 'i',
+        description = "a file of post or user urls to download, one per line"
+    )]
+    pub input_file: Option<PathBuf>,
+
+    #[argh(
+        switch,
+        long = "listing",
+        description = "download the posts of a listing, filtered with the listing options"
+    )]
+    pub listing: bool,
+
+    #[argh(
+        option,
+        long = "sort",
+        description = "how to sort the listing",
+        default = "Default::default()"
+    )]
+    pub sort: SortOrder,
+
+    #[argh(
+        option,
+        long = "search",
+        description = "only list posts matching this text"
+    )]
+    pub search: Option<String>,
+
+    #[argh(
+        option,
+        long = "nsfw",
+        description = "how to filter nsfw posts in the listing: include, only, or exclude"
+    )]
+    pub nsfw: Option<NsfwFilter>,
+
+    #[argh(
+        option,
+        long = "time",
+        description = "the timeframe of the listing: day, week, month, year, or all"
+    )]
+    pub time: Option<Timeframe>,
+
+    #[argh(
+        option,
+        long = "favorites",
+        description = "only list posts favorited by this user"
+    )]
+    pub favorites: Option<String>,
+
+    #[argh(
+        option,
+        long = "max-posts",
+        description = "the max number of posts to download from each user or listing"
+    )]
+    pub max_posts: Option<u64>,
 
     #[argh(
         option,
@@ -23,53 +151,703 @@ pub struct Options {
         description = "the directory to download to"
     )]
     pub out_dir: PathBuf,
+
+    #[argh(
+        option,
+        long = "dir-template",
+        description = "the directory to put each post in, relative to the out dir. Placeholders: {{post_id}}, {{title}}, {{username}}. Defaults to \"{{post_id}}\""
+    )]
+    pub dir_template: Option<Template>,
+
+    #[argh(
+        option,
+        long = "filename-template",
+        description = "the name of each file, relative to its post directory. Placeholders: {{post_id}}, {{title}}, {{username}}, {{position}}, {{file_id}}, {{ext}}, {{description}}, with an optional width like {{position:03}}. Defaults to the name in the file url"
+    )]
+    pub filename_template: Option<Template>,
+
+    #[argh(
+        option,
+        long = "media-kind",
+        description = "only download files of this kind: image, gif, video, or unknown. May be repeated"
+    )]
+    pub media_kinds: Vec<MediaKind>,
+
+    #[argh(
+        option,
+        long = "concurrent-posts",
+        default = "2",
+        description = "the max number of posts to download at once"
+    )]
+    pub concurrent_posts: usize,
+
+    #[argh(
+        option,
+        short = 'j',
+        long = "jobs",
+        default = "4",
+        description = "the max number of files to download at once"
+    )]
+    pub jobs: usize,
+
+    #[argh(
+        switch,
+        long = "verify",
+        description = "check existing files against the manifest of their post and download them again if they do not match"
+    )]
+    pub verify: bool,
+
+    #[argh(
+        switch,
+        long = "sidecars",
+        description = "write a json file with the post and file metadata next to each file"
+    )]
+    pub sidecars: bool,
+
+    #[argh(
+        switch,
+        long = "descriptions-file",
+        description = "write the file descriptions of each post to a \"descriptions.txt\" file"
+    )]
+    pub descriptions_file: bool,
+
+    #[argh(
+        switch,
+        long = "embed-metadata",
+        description = "embed the title, description, and source url into downloaded jpeg and png files as xmp"
+    )]
+    pub embed_metadata: bool,
+
+    #[argh(
+        option,
+        long = "archive",
+        description = "an archive database to record downloaded posts in. Posts already in it are skipped"
+    )]
+    pub archive: Option<PathBuf>,
+
+    #[argh(
+        option,
+        long = "state-file",
+        description = "the file used to resume interrupted downloads. Defaults to \".download-state.json\" in the out dir when downloading users, listings, or an input file"
+    )]
+    pub state_file: Option<PathBuf>,
+
+    #[argh(
+        switch,
+        long = "ignore-state",
+        description = "download posts again even if the state file says they are complete"
+    )]
+    pub ignore_state: bool,
+}
+
+/// Something to download posts from.
+#[derive(Debug)]
+enum Source {
+    Post(String),
+    User(String),
+    Listing(imgchest::ListPostsBuilder),
+}
+
+/// Shared state for downloading posts.
+#[derive(Debug)]
+struct PostContext {
+    out_dir: PathBuf,
+    media_kinds: Vec<imgchest::MediaKind>,
+    dir_template: Option<Template>,
+    filename_template: Option<Template>,
+    sidecars: bool,
+    descriptions_file: bool,
+    embed_metadata: bool,
+    verify: bool,
+    archive: Option<Archive>,
+
+    /// The state file, if one is used.
+    state: Option<Mutex<DownloadState>>,
+    ignore_state: bool,
+
+    /// Limits the number of posts downloaded at once.
+    post_semaphore: Arc<Semaphore>,
+    file_semaphore: Arc<Semaphore>,
+    progress: Progress,
+}
+
+/// The outcome of downloading files.
+#[derive(Debug, Default)]
+struct Summary {
+    downloaded: usize,
+    skipped: usize,
+    failed: Vec<anyhow::Error>,
+}
+
+impl Summary {
+    /// Add the outcome of another download to this one.
+    fn merge(&mut self, other: Self) {
+        self.downloaded += other.downloaded;
+        self.skipped += other.skipped;
+        self.failed.extend(other.failed);
+    }
 }
 
 pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
-    let id = extract_id(options.url.as_str()).context("failed to determine post id")?;
+    ensure!(
+        options.concurrent_posts > 0,
+        "concurrent-posts must be at least 1"
+    );
+    ensure!(options.jobs > 0, "jobs must be at least 1");
+    if let Some(dir_template) = options.dir_template.as_ref() {
+        ensure!(
+            !dir_template.has_file_fields(),
+            "the dir template can only use post placeholders"
+        );
+        ensure!(
+            dir_template.has_post_id(),
+            "the dir template must use {{post_id}}, so each post gets its own directory"
+        );
+    }
 
-    let post = client
-        .get_scraped_post(&id)
-        .await
-        .context("failed to get post")?;
+    let mut sources = Vec::new();
+    for target in options.targets.iter() {
+        sources.push(parse_target(target)?);
+    }
+    if let Some(input_file) = options.input_file.as_ref() {
+        let input = tokio::fs::read_to_string(input_file)
+            .await
+            .with_context(|| format!("failed to read \"{}\"", input_file.display()))?;
+        for line in input.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            sources.push(parse_target(line)?);
+        }
+    }
+    if options.listing {
+        let mut builder = imgchest::ListPostsBuilder::new();
+        builder.sort(options.sort.into());
+        if let Some(search) = options.search.clone() {
+            builder.search(search);
+        }
+        if let Some(nsfw) = options.nsfw {
+            builder.nsfw(nsfw.into());
+        }
+        if let Some(time) = options.time {
+            builder.timeframe(time.into());
+        }
+        if let Some(favorites) = options.favorites.clone() {
+            builder.favorites(favorites);
+        }
+        sources.push(Source::Listing(builder));
+    }
+    ensure!(!sources.is_empty(), "nothing to download");
+
+    let is_listing = sources
+        .iter()
+        .any(|source| !matches!(source, Source::Post(_)));
+    if is_listing {
+        crate::util::load_cookies(&client).await?;
+    }
+
+    // Without a state file, downloading a single post again fills in its missing files.
+    let state_path = match options.state_file.clone() {
+        Some(state_file) => Some(state_file),
+        None if is_listing || options.input_file.is_some() => {
+            Some(options.out_dir.join(".download-state.json"))
+        }
+        None => None,
+    };
+    let state = match state_path {
+        Some(state_path) => Some(Mutex::new(DownloadState::load(state_path).await?)),
+        None => None,
+    };
+
+    let archive = match options.archive.clone() {
+        Some(path) => Some(Archive::open(path).await?),
+        None => None,
+    };
+
+    let progress = Progress::new();
+    let context = Arc::new(PostContext {
+        out_dir: options.out_dir.clone(),
+        media_kinds: options
+            .media_kinds
+            .iter()
+            .copied()
+            .map(imgchest::MediaKind::from)
+            .collect(),
+        dir_template: options.dir_template.clone(),
+        filename_template: options.filename_template.clone(),
+        sidecars: options.sidecars,
+        descriptions_file: options.descriptions_file,
+        embed_metadata: options.embed_metadata,
+        verify: options.verify,
+        archive,
+        state,
+        ignore_state: options.ignore_state,
+        post_semaphore: Arc::new(Semaphore::new(options.concurrent_posts)),
+        file_semaphore: Arc::new(Semaphore::new(options.jobs)),
+        progress: progress.clone(),
+    });
+    let mut join_set = JoinSet::new();
+    let mut seen = HashSet::new();
+    let mut summary = Summary::default();
 
-    let out_dir = options.out_dir.join(&*post.id);
+    for source in sources {
+        let mut builder = match source {
+            Source::Post(id) => {
+                spawn_post_download(&client, &mut join_set, &context, &mut seen, id).await?;
+                continue;
+            }
+            Source::User(name) => {
+                let mut builder = imgchest::ListPostsBuilder::new();
+                builder.username(name).sort(imgchest::SortOrder::New);
+                builder
+            }
+            Source::Listing(builder) => builder,
+        };
+        if let Some(max_posts) = options.max_posts {
+            builder.max_items(max_posts);
+        }
+
+        let stream = client.list_posts_stream(builder);
+        let mut stream = std::pin::pin!(stream);
+        while let Some(result) = stream.next().await {
+            let post = match result {
+                Ok(post) => post,
+                Err(error) => {
+                    let error = anyhow::Error::from(error).context("failed to list posts");
+                    progress.eprintln(format!("{error:?}"));
+                    summary.failed.push(error);
+                    break;
+                }
+            };
+
+            spawn_post_download(&client, &mut join_set, &context, &mut seen, post.id.into())
+                .await?;
+
+            // Report finished posts while the listing is walked.
+            while let Some(result) = join_set.try_join_next() {
+                handle_post_result(result, &progress, &mut summary);
+            }
+        }
+    }
+
+    while let Some(result) = join_set.join_next().await {
+        handle_post_result(result, &progress, &mut summary);
+    }
+    progress.finish();
+
+    println!(
+        "Downloaded {} files, skipped {}, failed {}",
+        summary.downloaded,
+        summary.skipped,
+        summary.failed.len()
+    );
+    if !summary.failed.is_empty() {
+        eprintln!("Failures:");
+        for error in summary.failed.iter() {
+            eprintln!("  {error:#}");
+        }
+        bail!("{} downloads failed", summary.failed.len());
+    }
+
+    Ok(())
+}
+
+/// Parse a post or user url, or a post id.
+fn parse_target(target: &str) -> anyhow::Result<Source> {
+    if let Ok(id) = extract_id(target) {
+        return Ok(Source::Post(id));
+    }
+
+    let is_user_url = Url::parse(target).is_ok_and(|url| {
+        url.path_segments()
+            .and_then(|mut path| path.next())
+            .is_some_and(|segment| segment == "u")
+    });
+    if is_user_url {
+        let user = crate::command::profile::extract_user(target.to_string())?;
+        return Ok(Source::User(user));
+    }
+
+    bail!("\"{target}\" is not a post or user url or a post id")
+}
+
+/// Spawn a task to download a post, waiting until there is room to start it.
+async fn spawn_post_download(
+    client: &imgchest::Client,
+    join_set: &mut JoinSet<(String, anyhow::Result<Summary>)>,
+    context: &Arc<PostContext>,
+    seen: &mut HashSet<String>,
+    id: String,
+) -> anyhow::Result<()> {
+    if !seen.insert(id.clone()) {
+        return Ok(());
+    }
+
+    if !context.ignore_state && !context.verify {
+        if let Some(state) = context.state.as_ref() {
+            if state.lock().await.is_completed(&id) {
+                context
+                    .progress
+                    .println(format!("Skipping {id}, already downloaded"));
+                return Ok(());
+            }
+        }
+
+        if let Some(archive) = context.archive.as_ref() {
+            if archive.contains_post(&id).await? {
+                context
+                    .progress
+                    .println(format!("Skipping {id}, already archived"));
+                return Ok(());
+            }
+        }
+    }
+
+    let permit = context.post_semaphore.clone().acquire_owned().await?;
+    let client = client.clone();
+    let context = context.clone();
+    join_set.spawn(async move {
+        let result = async {
+            let summary = download_post(&client, &id, &context).await?;
+
+            if let Some(state) = context.state.as_ref().filter(|_| summary.failed.is_empty()) {
+                let mut state = state.lock().await;
+                state.mark_completed(&id).await.with_context(|| {
+                    format!("failed to save state to \"{}\"", state.path().display())
+                })?;
+            }
+
+            anyhow::Ok(summary)
+        }
+        .await;
+        drop(permit);
+
+        (id, result)
+    });
+
+    Ok(())
+}
+
+/// Report the result of a post download, adding it to the summary.
+fn handle_post_result(
+    result: Result<(String, anyhow::Result<Summary>), JoinError>,
+    progress: &Progress,
+    summary: &mut Summary,
+) {
+    let error = match result {
+        Ok((id, Ok(post_summary))) => {
+            if post_summary.failed.is_empty() {
+                progress.println(format!("Finished {id}"));
+            }
+            summary.merge(post_summary);
+            return;
+        }
+        Ok((id, Err(error))) => error.context(format!("failed to download {id}")),
+        Err(error) => anyhow::Error::from(error).context("failed to join tokio task"),
+    };
+
+    progress.eprintln(format!("{error:?}"));
+    summary.failed.push(error);
+}
+
+/// Download a single post.
+///
+/// Errors for individual files are recorded in the returned summary.
+async fn download_post(
+    client: &imgchest::Client,
+    id: &str,
+    context: &PostContext,
+) -> anyhow::Result<Summary> {
+    let post = get_post(client, id).await?;
+
+    let values = TemplateValues {
+        post_id: post.id(),
+        title: post.title(),
+        username: post.username(),
+        ..TemplateValues::default()
+    };
+    let out_dir = match context.dir_template.as_ref() {
+        Some(dir_template) => context.out_dir.join(dir_template.render(&values)),
+        None => context.out_dir.join(post.id()),
+    };
 
     tokio::fs::create_dir_all(&out_dir)
         .await
         .context("failed to create out dir")?;
 
-    let post_json = serde_json::to_string(&post)?;
-    tokio::fs::write(out_dir.join("post.json"), &post_json).await?;
+    let post_json = post.to_json_value()?;
+    let post_json_str = serde_json::to_string(&post_json)?;
+    tokio::fs::write(out_dir.join("post.json"), &post_json_str).await?;
+    let metadata = PostMetadata::new(post_json);
+    let source = client.base_url().join(&format!("p/{}", post.id()))?;
+
+    let mut manifest = Manifest::load(&out_dir).await?;
+
+    // Reserve the names of metadata files so downloaded files cannot replace them.
+    let mut deduplicator = PathDeduplicator::new();
+    deduplicator.dedup("post.json".into());
+    deduplicator.dedup(MANIFEST_FILE_NAME.into());
+    deduplicator.dedup(DESCRIPTIONS_FILE_NAME.into());
+
+    let mut files = post.files();
+    files.sort_by_key(|file| file.position);
 
     let mut join_set = JoinSet::new();
-    let total_downloads = post.image_count;
-    for image in post.images.iter() {
-        spawn_image_download(&client, &mut join_set, image, &out_dir);
+    let mut descriptions = Vec::new();
+    let mut archive_files = Vec::new();
+    for file in files {
+        if !context.media_kinds.is_empty() && !context.media_kinds.contains(&file.media_kind) {
+            continue;
+        }
+
+        let path = match context.filename_template.as_ref() {
+            Some(filename_template) => filename_template.render(&TemplateValues {
+                position: Some(file.position),
+                file_id: Some(file.id),
+                ext: file.extension,
+                description: file.description,
+                ..values
+            }),
+            None => file
+                .link
+                .split('/')
+                .next_back()
+                .unwrap_or(file.id)
+                .to_string(),
+        };
+        let path = deduplicator.dedup(path);
+
+        if let Some(description) = file.description {
+            descriptions.push((path.clone(), description));
+        }
+        archive_files.push(NewFile {
+            id: file.id.to_string(),
+            position: file.position,
+            link: file.link.to_string(),
+            description: file.description.map(String::from),
+            path: path.clone(),
+            size: 0,
+            sha256: String::new(),
+        });
+
+        let sidecar = if context.sidecars {
+            Some(metadata.sidecar(file.index, source.as_str(), &path)?)
+        } else {
+            None
+        };
+        let xmp = context.embed_metadata.then(|| XmpMetadata {
+            title: post.title().map(String::from),
+            description: file.description.map(String::from),
+            source: source.to_string(),
+        });
+
+        let manifest_entry = manifest.files.get(&path).cloned();
+        spawn_image_download(
+            client,
+            &mut join_set,
+            context,
+            &out_dir,
+            FileJob {
+                link: file.link.to_string(),
+                path,
+                manifest_entry,
+                sidecar,
+                xmp,
+            },
+        );
+    }
+
+    if context.descriptions_file {
+        let text = sidecar::descriptions_text(
+            descriptions
+                .iter()
+                .map(|(path, description)| (path.as_str(), *description)),
+        );
+        tokio::fs::write(out_dir.join(DESCRIPTIONS_FILE_NAME), text)
+            .await
+            .context("failed to write descriptions file")?;
     }
 
-    let mut last_error = Ok(());
-    let mut downloaded = 0;
+    let mut summary = Summary::default();
     while let Some(result) = join_set.join_next().await {
         match result
             .context("failed to join tokio task")
             .and_then(|result| result)
         {
-            Ok(_new_download) => {
-                downloaded += 1;
-                println!("{downloaded}/{total_downloads}...");
+            Ok((path, FileStatus::Downloaded(entry))) => {
+                manifest.files.insert(path, entry);
+                summary.downloaded += 1;
+            }
+            Ok((path, FileStatus::Skipped(entry))) => {
+                if let Some(entry) = entry {
+                    manifest.files.insert(path, entry);
+                }
+                summary.skipped += 1;
             }
             Err(error) => {
-                eprintln!("{error:?}");
-                last_error = Err(error);
+                let error = error.context(format!("failed to download a file of {id}"));
+                context.progress.eprintln(format!("{error:?}"));
+                summary.failed.push(error);
             }
         }
     }
 
-    last_error
+    manifest
+        .save(&out_dir)
+        .await
+        .context("failed to save manifest")?;
+
+    if let Some(archive) = context.archive.as_ref() {
+        if summary.failed.is_empty() {
+            // Every selected file should have a manifest entry by now.
+            // If one does not, leave the post out so it is not skipped later.
+            let files: Option<Vec<_>> = archive_files
+                .into_iter()
+                .map(|mut file| {
+                    let entry = manifest.files.get(&file.path)?;
+                    file.size = entry.size;
+                    file.sha256 = entry.sha256.clone();
+                    Some(file)
+                })
+                .collect();
+            let files = files.context("a file of the post is missing from the manifest")?;
+            let directory =
+                std::path::absolute(&out_dir).context("failed to get absolute post directory")?;
+            archive
+                .add_post(NewPost {
+                    id: post.id().to_string(),
+                    title: post.title().map(String::from),
+                    username: post.username().to_string(),
+                    nsfw: post.nsfw(),
+                    source: source.to_string(),
+                    directory: directory.to_string_lossy().into_owned(),
+                    metadata: post_json_str,
+                    files,
+                })
+                .await
+                .context("failed to add post to archive")?;
+        }
+    }
+
+    Ok(summary)
 }
 
-fn extract_id(value: &str) -> anyhow::Result<String> {
+/// A post, from either the api or scraping.
+#[derive(Debug)]
+enum FetchedPost {
+    Api(imgchest::Post),
+    Scraped(imgchest::ScrapedPost),
+}
+
+impl FetchedPost {
+    fn id(&self) -> &str {
+        match self {
+            Self::Api(post) => &post.id,
+            Self::Scraped(post) => &post.id,
+        }
+    }
+
+    fn title(&self) -> Option<&str> {
+        match self {
+            Self::Api(post) => post.title.as_deref(),
+            Self::Scraped(post) => Some(&post.title),
+        }
+    }
+
+    fn nsfw(&self) -> bool {
+        match self {
+            Self::Api(post) => post.nsfw,
+            Self::Scraped(post) => post.nsfw,
+        }
+    }
+
+    fn username(&self) -> &str {
+        match self {
+            Self::Api(post) => &post.username,
+            Self::Scraped(post) => &post.username,
+        }
+    }
+
+    /// Get the files of this post.
+    fn files(&self) -> Vec<PostFile<'_>> {
+        match self {
+            Self::Api(post) => post
+                .images
+                .iter()
+                .enumerate()
+                .map(|(index, file)| PostFile {
+                    index,
+                    id: &file.id,
+                    link: &file.link,
+                    position: file.position.get(),
+                    description: file.description.as_deref(),
+                    extension: file.extension(),
+                    media_kind: file.media_kind(),
+                })
+                .collect(),
+            Self::Scraped(post) => post
+                .images
+                .iter()
+                .enumerate()
+                .map(|(index, file)| PostFile {
+                    index,
+                    id: &file.id,
+                    link: &file.link,
+                    position: file.position,
+                    description: file.description.as_deref(),
+                    extension: file.extension(),
+                    media_kind: file.media_kind(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Get the json of this post.
+    ///
+    /// The api and scraped posts have different fields,
+    /// so a "kind" key of "api" or "scraped" records which one this is.
+    fn to_json_value(&self) -> serde_json::Result<serde_json::Value> {
+        let (kind, mut value) = match self {
+            Self::Api(post) => ("api", serde_json::to_value(post)?),
+            Self::Scraped(post) => ("scraped", serde_json::to_value(post)?),
+        };
+        if let Some(object) = value.as_object_mut() {
+            object.insert("kind".into(), kind.into());
+        }
+        Ok(value)
+    }
+}
+
+/// A file of a post, from either the api or scraping.
+#[derive(Debug)]
+struct PostFile<'a> {
+    /// The index of the file in the post.
+    index: usize,
+    id: &'a str,
+    link: &'a str,
+    position: u32,
+    description: Option<&'a str>,
+    extension: Option<&'a str>,
+    media_kind: imgchest::MediaKind,
+}
+
+/// Get a post from the api, falling back to scraping if there is no token.
+async fn get_post(client: &imgchest::Client, id: &str) -> anyhow::Result<FetchedPost> {
+    match client.get_post(id).await {
+        Ok(post) => Ok(FetchedPost::Api(post)),
+        Err(imgchest::Error::MissingToken) => {
+            let post = client
+                .get_scraped_post(id)
+                .await
+                .context("failed to get post")?;
+            Ok(FetchedPost::Scraped(post))
+        }
+        Err(error) => Err(error).context("failed to get post"),
+    }
+}
+
+pub fn extract_id(value: &str) -> anyhow::Result<String> {
     match Url::parse(value) {
         Ok(url) => {
             // Ensure the url is in the format:
@@ -94,7 +872,7 @@ fn extract_id(value: &str) -> anyhow::Result<String> {
 }
 
 /// Ids are composed of 11 lowercase alphanumeric chars.
-fn is_valid_id(value: &str) -> bool {
+pub fn is_valid_id(value: &str) -> bool {
     value.len() == 11 && value.chars().all(is_ascii_alphanumeric_lowercase)
 }
 
@@ -102,31 +880,127 @@ fn is_ascii_alphanumeric_lowercase(ch: char) -> bool {
     ch.is_ascii_digit() | ch.is_ascii_lowercase()
 }
 
+/// A file to download.
+#[derive(Debug)]
+struct FileJob {
+    link: String,
+
+    /// The path of the file, relative to the post directory.
+    path: String,
+
+    manifest_entry: Option<ManifestEntry>,
+
+    /// The sidecar json to write next to the file.
+    sidecar: Option<String>,
+
+    /// The metadata to embed into the file after downloading it.
+    xmp: Option<XmpMetadata>,
+}
+
+/// What happened to a file of a post.
+#[derive(Debug)]
+enum FileStatus {
+    /// The file was downloaded.
+    Downloaded(ManifestEntry),
+
+    /// The file already existed.
+    ///
+    /// This has a new manifest entry if the file was not in the manifest.
+    Skipped(Option<ManifestEntry>),
+}
+
+/// Spawn a task to download a file of a post, waiting until there is room to start it.
+///
+/// Existing files are skipped, unless verifying and they do not match the manifest.
+/// Skipped files without a manifest entry are hashed so they can be added to it.
+/// The task returns the path of the file relative to the post directory and what happened to it.
 fn spawn_image_download(
     client: &imgchest::Client,
-    join_set: &mut JoinSet<anyhow::Result<bool>>,
-    file: &imgchest::ScrapedPostFile,
+    join_set: &mut JoinSet<anyhow::Result<(String, FileStatus)>>,
+    context: &PostContext,
     out_dir: &Path,
+    job: FileJob,
 ) {
     let client = client.clone();
-    let link = file.link.clone();
-    let out_path_result = file
-        .link
-        .split('/')
-        .next_back()
-        .context("missing file name")
-        .map(|file_name| out_dir.join(file_name));
+    let out_path = out_dir.join(&job.path);
+    let file_semaphore = context.file_semaphore.clone();
+    let progress = context.progress.clone();
+    let verify = context.verify;
     join_set.spawn(async move {
-        let out_path = out_path_result?;
+        let _permit = file_semaphore.acquire_owned().await?;
+
+        let write_sidecar = || async {
+            if let Some(sidecar) = job.sidecar.as_ref() {
+                let sidecar_path = nd_util::with_push_extension(&out_path, "json");
+                tokio::fs::write(&sidecar_path, sidecar)
+                    .await
+                    .with_context(|| format!("failed to write \"{}\"", sidecar_path.display()))?;
+            }
+            anyhow::Ok(())
+        };
+
         if tokio::fs::try_exists(&out_path)
             .await
             .context("failed to check if file exists")?
         {
-            return Ok(false);
+            let is_valid = match (verify, job.manifest_entry.as_ref()) {
+                (false, _) => true,
+                (true, Some(entry)) => entry
+                    .verify(&out_path)
+                    .await
+                    .with_context(|| format!("failed to verify \"{}\"", out_path.display()))?,
+                (true, None) => false,
+            };
+            if is_valid {
+                let entry = match job.manifest_entry {
+                    Some(_) => None,
+                    None => {
+                        Some(ManifestEntry::from_path(&out_path).await.with_context(|| {
+                            format!("failed to hash \"{}\"", out_path.display())
+                        })?)
+                    }
+                };
+                write_sidecar().await?;
+                return Ok((job.path, FileStatus::Skipped(entry)));
+            }
+
+            progress.eprintln(format!(
+                "\"{}\" failed verification, downloading again",
+                out_path.display()
+            ));
+            tokio::fs::remove_file(&out_path).await?;
+        }
+
+        if let Some(parent) = out_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("failed to create file dir")?;
         }
 
-        nd_util::download_to_path(&client.client, &link, &out_path).await?;
+        let mut file_progress = progress.start_file(job.path.clone());
+        let mut entry = download_file(&client.client, &job.link, &out_path, &mut file_progress)
+            .await
+            .with_context(|| format!("failed to download \"{}\"", job.link))?;
+        file_progress.finish();
+
+        // The file is already downloaded, so failing to embed metadata is only a warning.
+        if let Some(xmp) = job.xmp.as_ref() {
+            match xmp::embed_in_file(&out_path, xmp).await {
+                Ok(true) => {
+                    entry = ManifestEntry::from_path(&out_path).await?;
+                }
+                Ok(false) => {}
+                Err(error) => {
+                    let error = error.context(format!(
+                        "failed to embed metadata in \"{}\"",
+                        out_path.display()
+                    ));
+                    progress.eprintln(format!("Warning: {error:?}"));
+                }
+            }
+        }
+        write_sidecar().await?;
 
-        Ok(true)
+        Ok((job.path, FileStatus::Downloaded(entry)))
     });
 }
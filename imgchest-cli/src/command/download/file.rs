@@ -0,0 +1,97 @@
+use super::manifest::ManifestEntry;
+use super::progress::FileProgress;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// Download a file to a path.
+///
+/// The file is downloaded to a ".part" file next to the path, which is renamed once the download is complete.
+/// If a ".part" file already exists, the download resumes from the end of it.
+pub async fn download_file(
+    client: &reqwest::Client,
+    url: &str,
+    path: &Path,
+    progress: &mut FileProgress,
+) -> anyhow::Result<ManifestEntry> {
+    let temp_path = nd_util::with_push_extension(path, "part");
+    let mut offset = match tokio::fs::metadata(&temp_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(error) => return Err(error).context("failed to check partial file"),
+    };
+
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let mut response = request.send().await?;
+
+    // The partial file is as large as or larger than the file, so it cannot be trusted.
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        offset = 0;
+        response = client.get(url).send().await?;
+    }
+    let mut response = response.error_for_status()?;
+
+    let resume = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    if resume {
+        let start = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_content_range_start);
+        if start != Some(offset) {
+            // Remove the partial file so the next attempt starts from scratch.
+            tokio::fs::remove_file(&temp_path).await?;
+            bail!("server returned an unexpected range");
+        }
+    } else {
+        offset = 0;
+    }
+
+    let expected_size = response.content_length().map(|len| offset + len);
+    progress.start(offset, expected_size);
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume)
+        .truncate(!resume)
+        .open(&temp_path)
+        .await
+        .context("failed to open partial file")?;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        progress.inc(u64::try_from(chunk.len())?);
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    drop(file);
+
+    let entry = ManifestEntry::from_path(&temp_path).await?;
+    if let Some(expected_size) = expected_size {
+        ensure!(
+            entry.size == expected_size,
+            "expected {expected_size} bytes, but got {} bytes",
+            entry.size
+        );
+    }
+
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .context("failed to rename partial file")?;
+
+    Ok(entry)
+}
+
+/// Parse the start of a "Content-Range" header, like "bytes 100-199/200".
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let range = value.strip_prefix("bytes ")?;
+    let (start, _rest) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
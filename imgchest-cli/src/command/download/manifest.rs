@@ -0,0 +1,87 @@
+use anyhow::Context;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+/// The name of the manifest file in a post directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A record of the files downloaded for a post, used to verify them later.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    /// The downloaded files, by file name.
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// A downloaded file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ManifestEntry {
+    /// The size of the file, in bytes.
+    pub size: u64,
+
+    /// The hex-encoded sha256 hash of the file.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// Load the manifest from a post directory, or create an empty one if it does not exist.
+    pub async fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(data) => serde_json::from_str(&data)
+                .with_context(|| format!("failed to parse \"{}\"", path.display())),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => {
+                Err(error).with_context(|| format!("failed to read \"{}\"", path.display()))
+            }
+        }
+    }
+
+    /// Save the manifest to a post directory, replacing the old manifest atomically.
+    pub async fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let data = serde_json::to_string_pretty(self)?;
+
+        let temp_path = nd_util::with_push_extension(&path, "tmp");
+        tokio::fs::write(&temp_path, data.as_bytes()).await?;
+        tokio::fs::rename(&temp_path, &path).await?;
+
+        Ok(())
+    }
+}
+
+impl ManifestEntry {
+    /// Create an entry for the file at the given path.
+    pub async fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            size += u64::try_from(n)?;
+        }
+
+        Ok(Self {
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+
+    /// Check if the file at the given path matches this entry.
+    pub async fn verify(&self, path: &Path) -> anyhow::Result<bool> {
+        let metadata = tokio::fs::metadata(path).await?;
+        if metadata.len() != self.size {
+            return Ok(false);
+        }
+
+        let entry = Self::from_path(path).await?;
+        Ok(entry == *self)
+    }
+}
@@ -0,0 +1,158 @@
+use indicatif::HumanBytes;
+use indicatif::MultiProgress;
+use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+const FILE_TEMPLATE: &str =
+    "{msg:32!} [{bar:24}] {bytes}/{total_bytes} {binary_bytes_per_sec} eta {eta}";
+// The total size of a run is not known until every post is listed and every file is started,
+// so the overall progress has no length or eta.
+const OVERALL_TEMPLATE: &str = "{spinner} total {bytes} {binary_bytes_per_sec} {msg} {elapsed}";
+
+/// Progress output for downloads.
+///
+/// Live progress bars are drawn to stderr if it is a terminal.
+/// Otherwise, a plain line is printed as each file finishes.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    multi: Option<MultiProgress>,
+    overall: ProgressBar,
+
+    /// The number of files that finished downloading.
+    files_done: Arc<AtomicU64>,
+}
+
+impl Progress {
+    /// Make a new progress output.
+    pub fn new() -> Self {
+        let files_done = Arc::new(AtomicU64::new(0));
+
+        let multi = MultiProgress::new();
+        if multi.is_hidden() {
+            return Self {
+                multi: None,
+                overall: ProgressBar::hidden(),
+                files_done,
+            };
+        }
+
+        let overall = multi.add(ProgressBar::no_length());
+        overall
+            .set_style(ProgressStyle::with_template(OVERALL_TEMPLATE).expect("invalid template"));
+        overall.set_message("0 files");
+        overall.enable_steady_tick(Duration::from_millis(200));
+
+        Self {
+            multi: Some(multi),
+            overall,
+            files_done,
+        }
+    }
+
+    /// Print a line to stdout without disturbing the progress bars.
+    pub fn println(&self, message: impl AsRef<str>) {
+        match self.multi.as_ref() {
+            Some(multi) => multi.suspend(|| println!("{}", message.as_ref())),
+            None => println!("{}", message.as_ref()),
+        }
+    }
+
+    /// Print a line to stderr without disturbing the progress bars.
+    pub fn eprintln(&self, message: impl AsRef<str>) {
+        match self.multi.as_ref() {
+            Some(multi) => multi.suspend(|| eprintln!("{}", message.as_ref())),
+            None => eprintln!("{}", message.as_ref()),
+        }
+    }
+
+    /// Start tracking the progress of a file download.
+    pub fn start_file(&self, name: String) -> FileProgress {
+        let bar = match self.multi.as_ref() {
+            Some(multi) => {
+                let bar = multi.insert_before(&self.overall, ProgressBar::new(0));
+                bar.set_style(
+                    ProgressStyle::with_template(FILE_TEMPLATE)
+                        .expect("invalid template")
+                        .progress_chars("=> "),
+                );
+                bar.set_message(name.clone());
+                bar
+            }
+            None => ProgressBar::hidden(),
+        };
+
+        FileProgress {
+            name,
+            bar,
+            overall: self.overall.clone(),
+            files_done: self.files_done.clone(),
+            is_terminal: self.multi.is_some(),
+            offset: 0,
+        }
+    }
+
+    /// Remove the progress bars.
+    pub fn finish(&self) {
+        self.overall.finish_and_clear();
+    }
+}
+
+/// The progress of a single file download.
+#[derive(Debug)]
+pub struct FileProgress {
+    name: String,
+    bar: ProgressBar,
+    overall: ProgressBar,
+    files_done: Arc<AtomicU64>,
+    is_terminal: bool,
+
+    /// The number of bytes downloaded before this attempt.
+    offset: u64,
+}
+
+impl FileProgress {
+    /// Start a download attempt, resuming at the given offset.
+    pub fn start(&mut self, offset: u64, total: Option<u64>) {
+        self.bar.reset();
+        if let Some(total) = total {
+            self.bar.set_length(total);
+        }
+
+        self.offset = offset;
+        self.bar.set_position(offset);
+    }
+
+    /// Record that bytes were downloaded.
+    pub fn inc(&mut self, delta: u64) {
+        self.bar.inc(delta);
+        self.overall.inc(delta);
+    }
+
+    /// Finish the download.
+    pub fn finish(&self) {
+        let files_done = self.files_done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.overall.set_message(format!("{files_done} files"));
+
+        if !self.is_terminal {
+            let size = self.bar.position();
+            let downloaded = size - self.offset;
+            let rate = downloaded as f64 / self.bar.elapsed().as_secs_f64().max(0.001);
+            println!(
+                "Downloaded {} ({}, {}/s)",
+                self.name,
+                HumanBytes(size),
+                HumanBytes(rate as u64)
+            );
+        }
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
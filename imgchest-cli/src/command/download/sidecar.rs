@@ -0,0 +1,56 @@
+use std::fmt::Write;
+
+/// The name of the descriptions file in a post directory.
+pub const DESCRIPTIONS_FILE_NAME: &str = "descriptions.txt";
+
+/// The metadata of a post, split into the post and its files.
+#[derive(Debug)]
+pub struct PostMetadata {
+    /// The post, without its files and comments.
+    pub post: serde_json::Value,
+
+    /// The files of the post, in the order the post lists them.
+    pub files: Vec<serde_json::Value>,
+}
+
+impl PostMetadata {
+    /// Split the json of a post.
+    pub fn new(mut post: serde_json::Value) -> Self {
+        let mut files = Vec::new();
+        if let Some(object) = post.as_object_mut() {
+            if let Some(serde_json::Value::Array(images)) = object.remove("images") {
+                files = images;
+            }
+            object.remove("comments");
+        }
+
+        Self { post, files }
+    }
+
+    /// Build the sidecar json for the file at the given index.
+    pub fn sidecar(&self, index: usize, source: &str, path: &str) -> serde_json::Result<String> {
+        let sidecar = serde_json::json!({
+            "source": source,
+            "path": path,
+            "post": self.post,
+            "file": self.files.get(index),
+        });
+        serde_json::to_string_pretty(&sidecar)
+    }
+}
+
+/// Build the text of a descriptions file from file paths and their descriptions.
+pub fn descriptions_text<'a, I>(descriptions: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut text = String::new();
+    for (path, description) in descriptions {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        let _ = writeln!(text, "{path}");
+        let _ = writeln!(text, "{}", description.trim_end());
+    }
+    text
+}
@@ -0,0 +1,65 @@
+use anyhow::Context;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The state of a download run, used to resume interrupted runs.
+#[derive(Debug)]
+pub struct DownloadState {
+    path: PathBuf,
+    data: DownloadStateData,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct DownloadStateData {
+    /// The ids of posts that were fully downloaded.
+    completed: BTreeSet<String>,
+}
+
+impl DownloadState {
+    /// Load the state from a path, or create an empty state if it does not exist.
+    pub async fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let data = match tokio::fs::read_to_string(&path).await {
+            Ok(data) => serde_json::from_str(&data)
+                .with_context(|| format!("failed to parse \"{}\"", path.display()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                DownloadStateData::default()
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read \"{}\"", path.display()))
+            }
+        };
+
+        Ok(Self { path, data })
+    }
+
+    /// Check if a post was fully downloaded.
+    pub fn is_completed(&self, id: &str) -> bool {
+        self.data.completed.contains(id)
+    }
+
+    /// Mark a post as fully downloaded and save the state.
+    pub async fn mark_completed(&mut self, id: &str) -> anyhow::Result<()> {
+        self.data.completed.insert(id.to_string());
+        self.save().await
+    }
+
+    /// Save the state, replacing the old state atomically.
+    async fn save(&self) -> anyhow::Result<()> {
+        let data = serde_json::to_string(&self.data)?;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp_path = nd_util::with_push_extension(&self.path, "tmp");
+        tokio::fs::write(&temp_path, data.as_bytes()).await?;
+        tokio::fs::rename(&temp_path, &self.path).await?;
+
+        Ok(())
+    }
+
+    /// Get the path of this state.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
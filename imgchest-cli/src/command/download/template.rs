@@ -0,0 +1,383 @@
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// The max length of a single placeholder value, in bytes.
+const MAX_VALUE_LEN: usize = 100;
+
+/// Names that cannot be used for files on Windows.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// A template for a relative path, like "{post_id}/{position:03}.{ext}".
+///
+/// Use "/" to separate directories, and "{{" and "}}" for literal braces.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field {
+        field: Field,
+        width: usize,
+        zero_pad: bool,
+    },
+}
+
+/// A template placeholder.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Field {
+    PostId,
+    Title,
+    Username,
+    Position,
+    FileId,
+    Ext,
+    Description,
+}
+
+impl Field {
+    /// Whether this field refers to a file, rather than a post.
+    fn is_file_field(self) -> bool {
+        matches!(
+            self,
+            Self::Position | Self::FileId | Self::Ext | Self::Description
+        )
+    }
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "post_id" => Ok(Self::PostId),
+            "title" => Ok(Self::Title),
+            "username" => Ok(Self::Username),
+            "position" => Ok(Self::Position),
+            "file_id" => Ok(Self::FileId),
+            "ext" => Ok(Self::Ext),
+            "description" => Ok(Self::Description),
+            _ => bail!("unknown placeholder \"{input}\""),
+        }
+    }
+}
+
+/// The values used to render a template.
+#[derive(Debug, Default)]
+pub struct TemplateValues<'a> {
+    pub post_id: &'a str,
+    pub title: Option<&'a str>,
+    pub username: &'a str,
+    pub position: Option<u32>,
+    pub file_id: Option<&'a str>,
+    pub ext: Option<&'a str>,
+    pub description: Option<&'a str>,
+}
+
+impl Template {
+    /// Check if this template uses any placeholders that refer to a file.
+    pub fn has_file_fields(&self) -> bool {
+        self.segments.iter().any(|segment| match segment {
+            Segment::Field { field, .. } => field.is_file_field(),
+            Segment::Literal(_) => false,
+        })
+    }
+
+    /// Check if this template uses the post id.
+    pub fn has_post_id(&self) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(
+                segment,
+                Segment::Field {
+                    field: Field::PostId,
+                    ..
+                }
+            )
+        })
+    }
+
+    /// Render this template into a relative path, with components separated by "/".
+    ///
+    /// Placeholder values are sanitized so they cannot add directories,
+    /// and each path component is made safe to use as a file name.
+    pub fn render(&self, values: &TemplateValues) -> String {
+        let mut rendered = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Field {
+                    field,
+                    width,
+                    zero_pad,
+                } => {
+                    let value = match field {
+                        Field::PostId => sanitize_value(values.post_id),
+                        Field::Title => sanitize_value(values.title.unwrap_or("")),
+                        Field::Username => sanitize_value(values.username),
+                        Field::Position => values
+                            .position
+                            .map(|position| position.to_string())
+                            .unwrap_or_default(),
+                        Field::FileId => sanitize_value(values.file_id.unwrap_or("")),
+                        Field::Ext => sanitize_value(values.ext.unwrap_or("")),
+                        Field::Description => sanitize_value(values.description.unwrap_or("")),
+                    };
+                    let fill = if *zero_pad { '0' } else { ' ' };
+                    for _ in value.chars().count()..*width {
+                        rendered.push(fill);
+                    }
+                    rendered.push_str(&value);
+                }
+            }
+        }
+
+        rendered
+            .split('/')
+            .map(sanitize_component)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = input.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let (placeholder, after) = rest
+                        .split_once('}')
+                        .with_context(|| format!("unclosed placeholder in \"{input}\""))?;
+                    chars = after.chars();
+
+                    let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+                    let field: Field = name.parse()?;
+                    let zero_pad = spec.starts_with('0');
+                    let width = if spec.is_empty() {
+                        0
+                    } else {
+                        spec.parse()
+                            .with_context(|| format!("invalid width \"{spec}\" for \"{name}\""))?
+                    };
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field {
+                        field,
+                        width,
+                        zero_pad,
+                    });
+                }
+                '}' => bail!("unmatched \"}}\" in \"{input}\""),
+                _ => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        let template = Self { segments };
+        ensure!(
+            template
+                .segments
+                .iter()
+                .any(|segment| matches!(segment, Segment::Field { .. })),
+            "template \"{input}\" has no placeholders"
+        );
+
+        Ok(template)
+    }
+}
+
+/// Make a placeholder value safe to put in a path component.
+fn sanitize_value(value: &str) -> String {
+    let mut sanitized = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if sanitized.len() + ch.len_utf8() > MAX_VALUE_LEN {
+            break;
+        }
+
+        let ch = match ch {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            ch if ch.is_whitespace() => ' ',
+            ch if ch.is_control() => '_',
+            ch => ch,
+        };
+        sanitized.push(ch);
+    }
+
+    sanitized
+}
+
+/// Make a path component safe to use as a file name.
+fn sanitize_component(component: &str) -> String {
+    let component = component.trim().trim_end_matches('.');
+    if component.is_empty() || component == "." || component == ".." {
+        return "_".into();
+    }
+
+    let stem = component
+        .split_once('.')
+        .map_or(component, |(stem, _extension)| stem);
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(stem))
+    {
+        return format!("_{component}");
+    }
+
+    component.into()
+}
+
+/// Tracks the paths used for the files of a post, renaming files that would collide.
+#[derive(Debug, Default)]
+pub struct PathDeduplicator {
+    used: HashSet<String>,
+}
+
+impl PathDeduplicator {
+    /// Make a new, empty deduplicator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a unique version of the given relative path.
+    ///
+    /// Paths are compared case-insensitively, as some filesystems do.
+    /// Colliding paths get a number appended to their file stem, like "file (2).png".
+    pub fn dedup(&mut self, path: String) -> String {
+        if self.used.insert(path.to_lowercase()) {
+            return path;
+        }
+
+        let (dir, file_name) = match path.rsplit_once('/') {
+            Some((dir, file_name)) => (Some(dir), file_name),
+            None => (None, path.as_str()),
+        };
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+            _ => (file_name, None),
+        };
+        (2..)
+            .map(|i| {
+                let file_name = match extension {
+                    Some(extension) => format!("{stem} ({i}).{extension}"),
+                    None => format!("{stem} ({i})"),
+                };
+                match dir {
+                    Some(dir) => format!("{dir}/{file_name}"),
+                    None => file_name,
+                }
+            })
+            .find(|new_path| self.used.insert(new_path.to_lowercase()))
+            .expect("ran out of file names")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values() -> TemplateValues<'static> {
+        TemplateValues {
+            post_id: "3qe4gdvj4j2",
+            title: Some("Donkey Kong"),
+            username: "LunarLandr",
+            position: Some(7),
+            file_id: Some("nw7w6cmlvye"),
+            ext: Some("png"),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn template_render() {
+        let template: Template = "{username}/{post_id}/{position:03}-{file_id}.{ext}"
+            .parse()
+            .unwrap();
+        assert!(template.has_file_fields());
+        assert!(template.has_post_id());
+        assert!(template.render(&values()) == "LunarLandr/3qe4gdvj4j2/007-nw7w6cmlvye.png");
+
+        let template: Template = "{{{title}}} {description}x".parse().unwrap();
+        assert!(!template.has_post_id());
+        assert!(template.render(&values()) == "{Donkey Kong} x");
+
+        let template: Template = "{post_id} - {title}".parse().unwrap();
+        assert!(!template.has_file_fields());
+        assert!(template.render(&values()) == "3qe4gdvj4j2 - Donkey Kong");
+    }
+
+    #[test]
+    fn template_parse_errors() {
+        for input in [
+            "no placeholders",
+            "{{escaped}}",
+            "{unknown}",
+            "{post_id",
+            "post_id}",
+            "{position:wide}",
+        ] {
+            assert!(input.parse::<Template>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn template_render_sanitizes_values() {
+        let template: Template = "{title}/{post_id}".parse().unwrap();
+        let render = |title| {
+            template.render(&TemplateValues {
+                title: Some(title),
+                ..values()
+            })
+        };
+
+        assert!(render("../../etc/passwd") == ".._.._etc_passwd/3qe4gdvj4j2");
+        assert!(render("..") == "_/3qe4gdvj4j2");
+        assert!(render("  ") == "_/3qe4gdvj4j2");
+        assert!(render("a:b*c?\n") == "a_b_c_/3qe4gdvj4j2");
+        assert!(render("CON.txt") == "_CON.txt/3qe4gdvj4j2");
+        assert!(render("trailing dot.") == "trailing dot/3qe4gdvj4j2");
+        assert!(render(&"a".repeat(150)) == format!("{}/3qe4gdvj4j2", "a".repeat(100)));
+    }
+
+    #[test]
+    fn sanitize_value_truncates_on_char_boundary() {
+        let value = "é".repeat(MAX_VALUE_LEN);
+        let sanitized = sanitize_value(&value);
+        assert!(sanitized.len() == MAX_VALUE_LEN);
+        assert!(sanitized.chars().all(|ch| ch == 'é'));
+    }
+
+    #[test]
+    fn path_deduplicator() {
+        let mut deduplicator = PathDeduplicator::new();
+        assert!(deduplicator.dedup("a.png".into()) == "a.png");
+        assert!(deduplicator.dedup("A.png".into()) == "A (2).png");
+        assert!(deduplicator.dedup("a.png".into()) == "a (3).png");
+        assert!(deduplicator.dedup("dir/.hidden".into()) == "dir/.hidden");
+        assert!(deduplicator.dedup("dir/.hidden".into()) == "dir/.hidden (2)");
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use std::path::Path;
+
+/// The header of a JPEG APP1 segment holding XMP.
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// The keyword of a PNG iTXt chunk holding XMP.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Metadata to embed into an image as XMP.
+#[derive(Debug, Clone)]
+pub struct XmpMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub source: String,
+}
+
+impl XmpMetadata {
+    /// Build an XMP packet with this metadata.
+    fn to_packet(&self) -> String {
+        let mut packet = String::new();
+        packet.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        packet.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+        packet.push_str(" <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+        packet.push_str(
+            "  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n",
+        );
+        if let Some(title) = self.title.as_deref() {
+            packet.push_str(&format!(
+                "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+                escape_xml(title)
+            ));
+        }
+        if let Some(description) = self.description.as_deref() {
+            packet.push_str(&format!(
+                "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+                escape_xml(description)
+            ));
+        }
+        packet.push_str(&format!(
+            "   <dc:source>{}</dc:source>\n",
+            escape_xml(&self.source)
+        ));
+        packet.push_str("  </rdf:Description>\n");
+        packet.push_str(" </rdf:RDF>\n");
+        packet.push_str("</x:xmpmeta>\n");
+        packet.push_str("<?xpacket end=\"w\"?>");
+        packet
+    }
+}
+
+/// Embed metadata into the image at the given path, replacing any existing XMP.
+///
+/// Only JPEG and PNG images are supported.
+/// Returns false if the image is in an unsupported format.
+pub async fn embed_in_file(path: &Path, metadata: &XmpMetadata) -> anyhow::Result<bool> {
+    let data = tokio::fs::read(path).await?;
+    let packet = metadata.to_packet();
+    let new_data = if data.starts_with(&[0xFF, 0xD8]) {
+        embed_jpeg(&data, &packet).context("failed to embed xmp in jpeg")?
+    } else if data.starts_with(PNG_SIGNATURE) {
+        embed_png(&data, &packet).context("failed to embed xmp in png")?
+    } else {
+        return Ok(false);
+    };
+
+    let temp_path = nd_util::with_push_extension(path, "tmp");
+    tokio::fs::write(&temp_path, &new_data).await?;
+    tokio::fs::rename(&temp_path, path).await?;
+
+    Ok(true)
+}
+
+/// Embed an XMP packet into a JPEG, as an APP1 segment after the leading APP segments.
+fn embed_jpeg(data: &[u8], packet: &str) -> anyhow::Result<Vec<u8>> {
+    let segment_len = 2 + JPEG_XMP_HEADER.len() + packet.len();
+    let segment_len = u16::try_from(segment_len).context("xmp packet is too large")?;
+
+    let mut new_data = Vec::with_capacity(data.len() + usize::from(segment_len) + 2);
+    new_data.extend_from_slice(&data[..2]);
+
+    let mut pos = 2;
+    loop {
+        // Markers may be padded with any number of fill bytes.
+        while data.get(pos..pos + 2) == Some(&[0xFF, 0xFF]) {
+            pos += 1;
+        }
+
+        let marker = match data.get(pos..pos + 2) {
+            Some(&[0xFF, marker]) => marker,
+            Some(_) => bail!("invalid segment marker"),
+            None => bail!("unexpected end of file"),
+        };
+        if !(0xE0..=0xEF).contains(&marker) {
+            break;
+        }
+
+        let len = data
+            .get(pos + 2..pos + 4)
+            .context("unexpected end of file")?;
+        let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+        if len < 2 {
+            bail!("invalid segment length {len}");
+        }
+        let end = pos + 2 + len;
+        let segment = data.get(pos..end).context("unexpected end of file")?;
+
+        let is_xmp = marker == 0xE1 && segment[4..].starts_with(JPEG_XMP_HEADER);
+        if !is_xmp {
+            new_data.extend_from_slice(segment);
+        }
+        pos = end;
+    }
+
+    new_data.extend_from_slice(&[0xFF, 0xE1]);
+    new_data.extend_from_slice(&segment_len.to_be_bytes());
+    new_data.extend_from_slice(JPEG_XMP_HEADER);
+    new_data.extend_from_slice(packet.as_bytes());
+    new_data.extend_from_slice(&data[pos..]);
+
+    Ok(new_data)
+}
+
+/// Embed an XMP packet into a PNG, as an iTXt chunk after the IHDR chunk.
+fn embed_png(data: &[u8], packet: &str) -> anyhow::Result<Vec<u8>> {
+    let mut chunk_data = Vec::with_capacity(PNG_XMP_KEYWORD.len() + 5 + packet.len());
+    chunk_data.extend_from_slice(PNG_XMP_KEYWORD);
+    // The null separator, no compression, the compression method,
+    // and the empty language tag and translated keyword.
+    chunk_data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    chunk_data.extend_from_slice(packet.as_bytes());
+
+    let mut new_data = Vec::with_capacity(data.len() + chunk_data.len() + 12);
+    new_data.extend_from_slice(PNG_SIGNATURE);
+
+    let mut pos = PNG_SIGNATURE.len();
+    let mut wrote_xmp = false;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).context("unexpected end of file")?;
+        let len = usize::try_from(u32::from_be_bytes([
+            header[0], header[1], header[2], header[3],
+        ]))?;
+        let chunk_type = &header[4..8];
+        let end = pos + 12 + len;
+        let chunk = data.get(pos..end).context("unexpected end of file")?;
+
+        let is_xmp = chunk_type == b"iTXt"
+            && chunk[8..].starts_with(PNG_XMP_KEYWORD)
+            && chunk.get(8 + PNG_XMP_KEYWORD.len()) == Some(&0);
+        if !is_xmp {
+            new_data.extend_from_slice(chunk);
+        }
+        pos = end;
+
+        if chunk_type == b"IHDR" {
+            write_png_chunk(&mut new_data, b"iTXt", &chunk_data)?;
+            wrote_xmp = true;
+        }
+    }
+    ensure!(wrote_xmp, "missing IHDR chunk");
+
+    Ok(new_data)
+}
+
+/// Write a PNG chunk.
+fn write_png_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) -> anyhow::Result<()> {
+    let len = u32::try_from(data.len()).context("chunk is too large")?;
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(chunk_type);
+    out.extend_from_slice(data);
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(chunk_type);
+    hasher.update(data);
+    out.extend_from_slice(&hasher.finalize().to_be_bytes());
+
+    Ok(())
+}
+
+/// Escape text for use in xml.
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A jpeg with an APP0 segment and no image data.
+    const JPEG: &[u8] = &[
+        0xFF, 0xD8, // SOI
+        0xFF, 0xE0, 0x00, 0x07, b'J', b'F', b'I', b'F', 0x00, // APP0
+        0xFF, 0xD9, // EOI
+    ];
+
+    fn png() -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]).unwrap();
+        write_png_chunk(&mut png, b"IEND", &[]).unwrap();
+        png
+    }
+
+    /// Split a jpeg into its leading APP segments, checking their lengths.
+    fn jpeg_segments(data: &[u8]) -> Vec<&[u8]> {
+        let mut segments = Vec::new();
+        let mut pos = 2;
+        while (0xE0..=0xEF).contains(&data[pos + 1]) {
+            let len = usize::from(u16::from_be_bytes([data[pos + 2], data[pos + 3]]));
+            assert!(len >= 2);
+            segments.push(&data[pos..pos + 2 + len]);
+            pos += 2 + len;
+        }
+        assert!(data[pos..] == [0xFF, 0xD9]);
+        segments
+    }
+
+    /// Split a png into its chunks, checking their lengths and crcs.
+    fn png_chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        assert!(data.starts_with(PNG_SIGNATURE));
+        let mut chunks = Vec::new();
+        let mut pos = PNG_SIGNATURE.len();
+        while pos < data.len() {
+            let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+            let len = usize::try_from(len).unwrap();
+            let chunk_type = &data[pos + 4..pos + 8];
+            let chunk_data = &data[pos + 8..pos + 8 + len];
+            let crc = u32::from_be_bytes(data[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(chunk_type);
+            hasher.update(chunk_data);
+            assert!(hasher.finalize() == crc);
+
+            chunks.push((chunk_type, chunk_data));
+            pos += 12 + len;
+        }
+        assert!(pos == data.len());
+        chunks
+    }
+
+    #[test]
+    fn embed_jpeg_replaces_xmp() {
+        let data = embed_jpeg(JPEG, "first").unwrap();
+        let segments = jpeg_segments(&data);
+        assert!(segments.len() == 2);
+        assert!(segments[0] == &JPEG[2..11]);
+        assert!(segments[1][4..].starts_with(JPEG_XMP_HEADER));
+        assert!(segments[1].ends_with(b"first"));
+
+        let data = embed_jpeg(&data, "second").unwrap();
+        let segments = jpeg_segments(&data);
+        assert!(segments.len() == 2);
+        assert!(segments[1].ends_with(b"second"));
+    }
+
+    #[test]
+    fn embed_jpeg_bad_segment_length() {
+        for len in [0, 1] {
+            let data = [0xFF, 0xD8, 0xFF, 0xE1, 0x00, len, 0xFF, 0xD9];
+            assert!(embed_jpeg(&data, "packet").is_err());
+        }
+    }
+
+    #[test]
+    fn embed_jpeg_padded_marker() {
+        let mut data = JPEG[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF]);
+        data.extend_from_slice(&JPEG[2..]);
+
+        let data = embed_jpeg(&data, "packet").unwrap();
+        let segments = jpeg_segments(&data);
+        assert!(segments.len() == 2);
+        assert!(segments[0] == &JPEG[2..11]);
+        assert!(segments[1].ends_with(b"packet"));
+    }
+
+    #[test]
+    fn embed_png_replaces_xmp() {
+        let data = embed_png(&png(), "first").unwrap();
+        let chunks = png_chunks(&data);
+        let types: Vec<_> = chunks.iter().map(|(chunk_type, _)| *chunk_type).collect();
+        assert!(types == [b"IHDR", b"iTXt", b"IEND"]);
+        assert!(chunks[1].1.starts_with(PNG_XMP_KEYWORD));
+        assert!(chunks[1].1.ends_with(b"first"));
+
+        let data = embed_png(&data, "second").unwrap();
+        let chunks = png_chunks(&data);
+        let types: Vec<_> = chunks.iter().map(|(chunk_type, _)| *chunk_type).collect();
+        assert!(types == [b"IHDR", b"iTXt", b"IEND"]);
+        assert!(chunks[1].1.ends_with(b"second"));
+    }
+
+    #[test]
+    fn escape_xml_special_chars() {
+        assert!(escape_xml("<a & 'b'>\"") == "&lt;a &amp; &apos;b&apos;&gt;&quot;");
+    }
+}
@@ -1,7 +1,5 @@
-use crate::UserConfig;
 use anyhow::bail;
 use anyhow::Context;
-use imgchest::Url;
 use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
@@ -29,6 +27,7 @@ pub enum SortOrder {
     Popular,
     Old,
     New,
+    Top,
 }
 
 impl FromStr for SortOrder {
@@ -39,6 +38,7 @@ impl FromStr for SortOrder {
             "popular" => Ok(Self::Popular),
             "old" => Ok(Self::Old),
             "new" => Ok(Self::New),
+            "top" => Ok(Self::Top),
             _ => bail!("unknown sort order \"{input}\""),
         }
     }
@@ -50,6 +50,73 @@ impl From<SortOrder> for imgchest::SortOrder {
             SortOrder::Popular => Self::Popular,
             SortOrder::Old => Self::Old,
             SortOrder::New => Self::New,
+            SortOrder::Top => Self::Top,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NsfwFilter {
+    Include,
+    Only,
+    Exclude,
+}
+
+impl FromStr for NsfwFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "include" => Ok(Self::Include),
+            "only" => Ok(Self::Only),
+            "exclude" => Ok(Self::Exclude),
+            _ => bail!("unknown nsfw filter \"{input}\""),
+        }
+    }
+}
+
+impl From<NsfwFilter> for imgchest::NsfwFilter {
+    fn from(nsfw: NsfwFilter) -> imgchest::NsfwFilter {
+        match nsfw {
+            NsfwFilter::Include => Self::Include,
+            NsfwFilter::Only => Self::Only,
+            NsfwFilter::Exclude => Self::Exclude,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Timeframe {
+    Day,
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl FromStr for Timeframe {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "year" => Ok(Self::Year),
+            "all" => Ok(Self::All),
+            _ => bail!("unknown timeframe \"{input}\""),
+        }
+    }
+}
+
+impl From<Timeframe> for imgchest::Timeframe {
+    fn from(timeframe: Timeframe) -> imgchest::Timeframe {
+        match timeframe {
+            Timeframe::Day => Self::Day,
+            Timeframe::Week => Self::Week,
+            Timeframe::Month => Self::Month,
+            Timeframe::Year => Self::Year,
+            Timeframe::All => Self::All,
         }
     }
 }
@@ -93,6 +160,34 @@ pub struct Options {
     )]
     profile: bool,
 
+    #[argh(
+        option,
+        long = "search",
+        description = "only include posts matching this text"
+    )]
+    search: Option<String>,
+
+    #[argh(
+        option,
+        long = "nsfw",
+        description = "how to filter nsfw posts: include, only, or exclude"
+    )]
+    nsfw: Option<NsfwFilter>,
+
+    #[argh(
+        option,
+        long = "time",
+        description = "the timeframe to list posts from: day, week, month, year, or all"
+    )]
+    time: Option<Timeframe>,
+
+    #[argh(
+        option,
+        long = "favorites",
+        description = "only include posts favorited by this user"
+    )]
+    favorites: Option<String>,
+
     #[argh(
         option,
         long = "output-format",
@@ -103,27 +198,7 @@ pub struct Options {
 }
 
 pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
-    let config_dir = crate::util::get_config_dir().await?;
-
-    let config_path = config_dir.join("config.toml");
-    let config_str = crate::util::read_or_init_user_config_str(&config_path).await?;
-
-    let user_config = UserConfig::new(&config_str)?;
-
-    if let Some(cookies) = user_config.get_cookies()? {
-        let mut cookie_store = client
-            .get_cookie_store()
-            .lock()
-            .expect("cookie store is poisoned");
-
-        for cookie in cookies.iter() {
-            let cookie = imgchest::RawCookie::parse(cookie)
-                .with_context(|| format!("failed to parse cookie \"{cookie}\""))?;
-            let url = Url::parse("https://imgchest.com/")?;
-
-            cookie_store.insert_raw(&cookie, &url)?;
-        }
-    }
+    crate::util::load_cookies(&client).await?;
 
     let mut builder = imgchest::ListPostsBuilder::new();
     builder
@@ -133,6 +208,18 @@ pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<
     if let Some(user) = options.user {
         builder.username(user);
     }
+    if let Some(search) = options.search {
+        builder.search(search);
+    }
+    if let Some(nsfw) = options.nsfw {
+        builder.nsfw(nsfw.into());
+    }
+    if let Some(time) = options.time {
+        builder.timeframe(time.into());
+    }
+    if let Some(favorites) = options.favorites {
+        builder.favorites(favorites);
+    }
 
     let posts = client
         .list_posts(builder)
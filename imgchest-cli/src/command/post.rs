@@ -0,0 +1,284 @@
+use crate::command::download::extract_id;
+use crate::command::download::is_valid_id;
+use crate::command::upload::Privacy;
+use anyhow::ensure;
+use anyhow::Context;
+use std::io::Write;
+use std::path::PathBuf;
+use url::Url;
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "post", description = "manage your posts")]
+pub struct Options {
+    #[argh(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand)]
+enum Subcommand {
+    Edit(EditOptions),
+    Delete(DeleteOptions),
+    Favorite(FavoriteOptions),
+    Add(AddOptions),
+    File(FileOptions),
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "edit", description = "edit a post")]
+pub struct EditOptions {
+    #[argh(positional, description = "the url or id of the post")]
+    pub post: String,
+
+    #[argh(option, long = "title", short = 't', description = "the new title")]
+    pub title: Option<String>,
+
+    #[argh(
+        option,
+        long = "privacy",
+        description = "the new privacy: public, hidden, or secret"
+    )]
+    pub privacy: Option<Privacy>,
+
+    #[argh(
+        option,
+        long = "nsfw",
+        description = "whether the post is nsfw: true or false"
+    )]
+    pub nsfw: Option<bool>,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "delete", description = "delete a post")]
+pub struct DeleteOptions {
+    #[argh(positional, description = "the url or id of the post")]
+    pub post: String,
+
+    #[argh(
+        switch,
+        long = "yes",
+        short = 'y',
+        description = "delete without asking for confirmation"
+    )]
+    pub yes: bool,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "favorite",
+    description = "favorite or unfavorite a post"
+)]
+pub struct FavoriteOptions {
+    #[argh(positional, description = "the url or id of the post")]
+    pub post: String,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "add", description = "add files to a post")]
+pub struct AddOptions {
+    #[argh(positional, description = "the url or id of the post")]
+    pub post: String,
+
+    #[argh(
+        positional,
+        description = "the files, directories, or glob patterns to upload"
+    )]
+    pub paths: Vec<String>,
+
+    #[argh(
+        option,
+        long = "descriptions",
+        description = "a json file mapping file names to descriptions"
+    )]
+    pub descriptions: Option<PathBuf>,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "file",
+    description = "manage the files of your posts"
+)]
+pub struct FileOptions {
+    #[argh(subcommand)]
+    subcommand: FileSubcommand,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand)]
+enum FileSubcommand {
+    Describe(FileDescribeOptions),
+    Delete(FileDeleteOptions),
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "describe",
+    description = "set the description of a file"
+)]
+pub struct FileDescribeOptions {
+    #[argh(positional, description = "the url or id of the file")]
+    pub file: String,
+
+    #[argh(positional, description = "the new description")]
+    pub description: String,
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(subcommand, name = "delete", description = "delete a file")]
+pub struct FileDeleteOptions {
+    #[argh(positional, description = "the url or id of the file")]
+    pub file: String,
+
+    #[argh(
+        switch,
+        long = "yes",
+        short = 'y',
+        description = "delete without asking for confirmation"
+    )]
+    pub yes: bool,
+}
+
+pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
+    match options.subcommand {
+        Subcommand::Edit(options) => {
+            let id = extract_id(&options.post).context("failed to determine post id")?;
+
+            let mut builder = imgchest::UpdatePostBuilder::new();
+            if let Some(title) = options.title {
+                builder.title(title);
+            }
+            if let Some(privacy) = options.privacy {
+                builder.privacy(privacy.into());
+            }
+            if let Some(nsfw) = options.nsfw {
+                builder.nsfw(nsfw);
+            }
+            ensure!(
+                builder.title.is_some() || builder.privacy.is_some() || builder.nsfw.is_some(),
+                "nothing to edit"
+            );
+
+            client
+                .update_post(&id, builder)
+                .await
+                .context("failed to edit post")?;
+            println!("Edited post {id}");
+        }
+        Subcommand::Delete(options) => {
+            let id = extract_id(&options.post).context("failed to determine post id")?;
+
+            if !options.yes && !confirm(format!("Delete post {id}?")).await? {
+                println!("Cancelled");
+                return Ok(());
+            }
+
+            client
+                .delete_post(&id)
+                .await
+                .context("failed to delete post")?;
+            println!("Deleted post {id}");
+        }
+        Subcommand::Favorite(options) => {
+            let id = extract_id(&options.post).context("failed to determine post id")?;
+
+            let added = client
+                .favorite_post(&id)
+                .await
+                .context("failed to favorite post")?;
+            if added {
+                println!("Favorited post {id}");
+            } else {
+                println!("Unfavorited post {id}");
+            }
+        }
+        Subcommand::Add(options) => {
+            let id = extract_id(&options.post).context("failed to determine post id")?;
+
+            let files = crate::command::upload::load_upload_files(
+                &options.paths,
+                options.descriptions.as_deref(),
+            )
+            .await?;
+            let num_files = files.len();
+
+            client
+                .add_post_images_batched(&id, files)
+                .await
+                .context("failed to add files to post")?;
+            println!("Added {num_files} files to post {id}");
+        }
+        Subcommand::File(options) => match options.subcommand {
+            FileSubcommand::Describe(options) => {
+                let id = extract_file_id(&options.file).context("failed to determine file id")?;
+
+                client
+                    .update_file(&id, &options.description)
+                    .await
+                    .context("failed to update file")?;
+                println!("Updated file {id}");
+            }
+            FileSubcommand::Delete(options) => {
+                let id = extract_file_id(&options.file).context("failed to determine file id")?;
+
+                if !options.yes && !confirm(format!("Delete file {id}?")).await? {
+                    println!("Cancelled");
+                    return Ok(());
+                }
+
+                client
+                    .delete_file(&id)
+                    .await
+                    .context("failed to delete file")?;
+                println!("Deleted file {id}");
+            }
+        },
+    }
+
+    Ok(())
+}
+
+/// Extract a file id from a file link or a raw id.
+fn extract_file_id(value: &str) -> anyhow::Result<String> {
+    match Url::parse(value) {
+        Ok(url) => {
+            // Ensure the url is in the format:
+            // https://cdn.imgchest.com/files/{id}.{extension}
+            ensure!(url.host_str() == Some("cdn.imgchest.com"));
+            let mut path_iter = url.path_segments().context("url is missing path")?;
+            ensure!(path_iter.next() == Some("files"));
+            let file_name = path_iter.next().context("url missing file path segment")?;
+            let id = file_name
+                .split_once('.')
+                .map_or(file_name, |(id, _extension)| id);
+            ensure!(is_valid_id(id), "invalid file id \"{id}\"");
+
+            Ok(id.to_string())
+        }
+        Err(_error) => {
+            ensure!(
+                is_valid_id(value),
+                "ids must be composed of 11 ascii alphanumeric characters"
+            );
+            Ok(value.to_string())
+        }
+    }
+}
+
+/// Ask the user to confirm an action.
+async fn confirm(prompt: String) -> anyhow::Result<bool> {
+    tokio::task::spawn_blocking(move || {
+        let mut stdout = std::io::stdout().lock();
+        write!(stdout, "{prompt} [y/N] ")?;
+        stdout.flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        Ok(input.eq_ignore_ascii_case("y") || input.eq_ignore_ascii_case("yes"))
+    })
+    .await?
+}
@@ -1,5 +1,6 @@
 use anyhow::ensure;
 use anyhow::Context;
+use time::OffsetDateTime;
 use url::Url;
 
 #[derive(Debug, argh::FromArgs)]
@@ -16,30 +17,93 @@ pub struct Options {
 pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
     let user = extract_user(options.user).context("failed to extract user")?;
 
-    let user = client
-        .get_scraped_user(&user)
-        .await
-        .context("failed to scrape user")?;
-
-    let created_date = user.created.date();
-
-    println!("Name: {}", user.name);
-    println!(
-        "Joined: {}/{}/{}",
-        created_date.month() as u8,
-        created_date.day(),
-        created_date.year()
-    );
-    println!("XP: {}", PrettyFormatU64(user.experience));
-    println!("Posts: {}", user.posts);
-    println!("Comments: {}", user.comments);
-    println!("Favorites: {}", user.favorites);
-    println!("Post Views: {}", PrettyFormatU64(user.post_views));
+    let profile = match client.get_user(&user).await {
+        Ok(user) => Profile::from(user),
+        Err(imgchest::Error::MissingToken) => client
+            .get_scraped_user(&user)
+            .await
+            .context("failed to scrape user")?
+            .into(),
+        Err(error) => return Err(error).context("failed to get user"),
+    };
+    profile.print();
 
     Ok(())
 }
 
-fn extract_user(input: String) -> anyhow::Result<String> {
+/// The profile of a user, from either the api or scraping.
+#[derive(Debug)]
+struct Profile {
+    name: Box<str>,
+    created: OffsetDateTime,
+    posts: u64,
+    comments: u64,
+
+    /// Only known when scraping.
+    experience: Option<u64>,
+
+    /// Only known when scraping.
+    favorites: Option<u64>,
+
+    /// Only known when scraping.
+    post_views: Option<u64>,
+}
+
+impl Profile {
+    /// Print this profile, skipping fields that are not known.
+    fn print(&self) {
+        let created_date = self.created.date();
+
+        println!("Name: {}", self.name);
+        println!(
+            "Joined: {}/{}/{}",
+            created_date.month() as u8,
+            created_date.day(),
+            created_date.year()
+        );
+        if let Some(experience) = self.experience {
+            println!("XP: {}", PrettyFormatU64(experience));
+        }
+        println!("Posts: {}", self.posts);
+        println!("Comments: {}", self.comments);
+        if let Some(favorites) = self.favorites {
+            println!("Favorites: {favorites}");
+        }
+        if let Some(post_views) = self.post_views {
+            println!("Post Views: {}", PrettyFormatU64(post_views));
+        }
+    }
+}
+
+impl From<imgchest::User> for Profile {
+    fn from(user: imgchest::User) -> Self {
+        Self {
+            name: user.name,
+            created: user.created,
+            posts: user.posts,
+            comments: user.comments,
+            experience: None,
+            favorites: None,
+            post_views: None,
+        }
+    }
+}
+
+impl From<imgchest::ScrapedUser> for Profile {
+    fn from(user: imgchest::ScrapedUser) -> Self {
+        Self {
+            name: user.name,
+            created: user.created,
+            posts: user.posts,
+            comments: user.comments,
+            experience: Some(user.experience),
+            favorites: Some(user.favorites),
+            post_views: Some(user.post_views),
+        }
+    }
+}
+
+pub fn extract_user(input: String) -> anyhow::Result<String> {
     match Url::parse(input.as_str()) {
         Ok(url) => {
             // Validate url.
@@ -0,0 +1,246 @@
+use crate::command::list_posts::OutputFormat;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Privacy {
+    Public,
+    Hidden,
+    Secret,
+}
+
+impl FromStr for Privacy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "public" => Ok(Self::Public),
+            "hidden" => Ok(Self::Hidden),
+            "secret" => Ok(Self::Secret),
+            _ => bail!("unknown privacy \"{input}\""),
+        }
+    }
+}
+
+impl From<Privacy> for imgchest::PostPrivacy {
+    fn from(privacy: Privacy) -> imgchest::PostPrivacy {
+        match privacy {
+            Privacy::Public => Self::Public,
+            Privacy::Hidden => Self::Hidden,
+            Privacy::Secret => Self::Secret,
+        }
+    }
+}
+
+#[derive(Debug, argh::FromArgs)]
+#[argh(
+    subcommand,
+    name = "upload",
+    description = "upload files as a new post"
+)]
+pub struct Options {
+    #[argh(
+        positional,
+        description = "the files, directories, or glob patterns to upload"
+    )]
+    paths: Vec<String>,
+
+    #[argh(option, long = "title", short = 't', description = "the post title")]
+    title: Option<String>,
+
+    #[argh(
+        option,
+        long = "privacy",
+        description = "the post privacy: public, hidden, or secret"
+    )]
+    privacy: Option<Privacy>,
+
+    #[argh(switch, long = "nsfw", description = "whether the post is nsfw")]
+    nsfw: bool,
+
+    #[argh(
+        switch,
+        long = "anonymous",
+        description = "whether the post should not be tied to your account"
+    )]
+    anonymous: bool,
+
+    #[argh(
+        option,
+        long = "descriptions",
+        description = "a json file mapping file names to descriptions"
+    )]
+    descriptions: Option<PathBuf>,
+
+    #[argh(
+        option,
+        long = "output-format",
+        default = "Default::default()",
+        description = "the output format"
+    )]
+    output_format: OutputFormat,
+}
+
+pub async fn exec(client: imgchest::Client, options: Options) -> anyhow::Result<()> {
+    let files = load_upload_files(&options.paths, options.descriptions.as_deref()).await?;
+
+    let mut builder = imgchest::CreatePostBuilder::new();
+    if let Some(title) = options.title {
+        builder.title(title);
+    }
+    if let Some(privacy) = options.privacy {
+        builder.privacy(privacy.into());
+    }
+    if options.nsfw {
+        builder.nsfw(true);
+    }
+    if options.anonymous {
+        builder.anonymous(true);
+    }
+    let num_files = files.len();
+    for file in files {
+        builder.image(file);
+    }
+
+    let post = match client.create_post_batched(builder).await {
+        Ok(post) => post,
+        Err(imgchest::Error::MissingToken) => {
+            bail!("uploading requires an api token, set one with --token, IMGCHEST_TOKEN, or the api-key config key")
+        }
+        Err(error) => {
+            // Tell the user where the partial upload went, so they can finish it with "post add".
+            if let imgchest::Error::BatchUpload {
+                post_id: Some(post_id),
+                uploaded,
+                ..
+            } = &error
+            {
+                let link = client.base_url().join(&format!("p/{post_id}"))?;
+                eprintln!(
+                    "Created post {link}, but only {uploaded} of {num_files} files were uploaded"
+                );
+                eprintln!("The rest can be added with \"imgchest post add {post_id} <paths>\"");
+            }
+            return Err(error).context("failed to upload post");
+        }
+    };
+
+    match options.output_format {
+        OutputFormat::Human => {
+            let link = client.base_url().join(&format!("p/{}", post.id))?;
+            println!("{link}");
+        }
+        OutputFormat::Json => {
+            let stdout = std::io::stdout().lock();
+            serde_json::to_writer(stdout, &post)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Expand path arguments into the files to upload,
+/// attaching descriptions from the given descriptions file.
+///
+/// Files are not kept open, so large directories can be uploaded.
+pub async fn load_upload_files(
+    inputs: &[String],
+    descriptions_path: Option<&Path>,
+) -> anyhow::Result<Vec<imgchest::UploadPostFile>> {
+    ensure!(!inputs.is_empty(), "need at least 1 path to upload");
+    let mut paths = Vec::new();
+    for input in inputs.iter() {
+        let expanded = expand_path(input)
+            .await
+            .with_context(|| format!("failed to expand \"{input}\""))?;
+        paths.extend(expanded);
+    }
+    ensure!(!paths.is_empty(), "no files to upload");
+
+    let descriptions = match descriptions_path {
+        Some(path) => read_descriptions(path)
+            .await
+            .with_context(|| format!("failed to read descriptions from \"{}\"", path.display()))?,
+        None => HashMap::new(),
+    };
+
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths.iter() {
+        let mut file = imgchest::UploadPostFile::from_path(path)
+            .await
+            .with_context(|| format!("failed to open \"{}\"", path.display()))?;
+        if let Some(description) = get_description(&descriptions, path) {
+            file.description(description);
+        }
+        files.push(file);
+    }
+
+    Ok(files)
+}
+
+/// Expand a path argument into the files it refers to.
+///
+/// Directories are expanded into the files they directly contain,
+/// and glob patterns are expanded into the files they match.
+/// Expanded files are sorted in natural order.
+async fn expand_path(input: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let path = Path::new(input);
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => Some(metadata),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut paths = match metadata {
+        Some(metadata) if metadata.is_dir() => {
+            let mut paths = Vec::new();
+            let mut entries = tokio::fs::read_dir(path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_file() {
+                    paths.push(entry.path());
+                }
+            }
+            paths
+        }
+        Some(_metadata) => return Ok(vec![path.to_path_buf()]),
+        None => {
+            let mut paths = Vec::new();
+            for entry in glob::glob(input)? {
+                let entry = entry?;
+                if entry.is_file() {
+                    paths.push(entry);
+                }
+            }
+            ensure!(!paths.is_empty(), "no files found");
+            paths
+        }
+    };
+
+    paths.sort_by(|a, b| crate::util::natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+    Ok(paths)
+}
+
+/// Read a json file mapping file names to descriptions.
+async fn read_descriptions(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let data = tokio::fs::read_to_string(path).await?;
+    let descriptions = serde_json::from_str(&data)?;
+    Ok(descriptions)
+}
+
+/// Get the description for a file, by its path as given or by its file name.
+fn get_description<'a>(descriptions: &'a HashMap<String, String>, path: &Path) -> Option<&'a str> {
+    let by_path = path.to_str().and_then(|path| descriptions.get(path));
+    let by_file_name = || {
+        path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| descriptions.get(file_name))
+    };
+
+    by_path.or_else(by_file_name).map(String::as_str)
+}
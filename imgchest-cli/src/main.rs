@@ -1,12 +1,21 @@
+mod archive;
 mod command;
 mod user_config;
 mod util;
 
 pub use self::user_config::UserConfig;
+use anyhow::Context;
 
 #[derive(Debug, argh::FromArgs)]
 #[argh(description = "a cli to interact with imgchest.com")]
 struct Options {
+    #[argh(
+        option,
+        long = "token",
+        description = "the api token to use, overriding the IMGCHEST_TOKEN env var and the config"
+    )]
+    token: Option<String>,
+
     #[argh(subcommand)]
     subcommand: Subcommand,
 }
@@ -14,10 +23,23 @@ struct Options {
 #[derive(Debug, argh::FromArgs)]
 #[argh(subcommand)]
 enum Subcommand {
+    Archive(self::command::archive::Options),
     Config(self::command::config::Options),
     Download(self::command::download::Options),
     Profile(self::command::profile::Options),
     ListPosts(self::command::list_posts::Options),
+    Upload(self::command::upload::Options),
+    Post(self::command::post::Options),
+}
+
+impl Subcommand {
+    /// Whether this subcommand uses the api token.
+    fn uses_token(&self) -> bool {
+        matches!(
+            self,
+            Self::Download(_) | Self::Profile(_) | Self::Upload(_) | Self::Post(_)
+        )
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -30,13 +52,55 @@ fn main() -> anyhow::Result<()> {
 
 async fn async_main(options: Options) -> anyhow::Result<()> {
     let client = imgchest::Client::new();
+    if options.subcommand.uses_token() {
+        if let Some(token) = load_token(options.token).await? {
+            client.set_token(token);
+        }
+    }
 
     match options.subcommand {
+        Subcommand::Archive(options) => self::command::archive::exec(options).await?,
         Subcommand::Config(options) => self::command::config::exec(options).await?,
         Subcommand::Download(options) => self::command::download::exec(client, options).await?,
         Subcommand::Profile(options) => self::command::profile::exec(client, options).await?,
         Subcommand::ListPosts(options) => self::command::list_posts::exec(client, options).await?,
+        Subcommand::Upload(options) => self::command::upload::exec(client, options).await?,
+        Subcommand::Post(options) => self::command::post::exec(client, options).await?,
     }
 
     Ok(())
 }
+
+/// Load the api token.
+///
+/// This is taken from the command line, the IMGCHEST_TOKEN env var, or the config, in that order.
+/// If the config cannot be loaded, a warning is printed and no token is used.
+async fn load_token(token: Option<String>) -> anyhow::Result<Option<String>> {
+    if let Some(token) = token {
+        return Ok(Some(token));
+    }
+
+    match std::env::var("IMGCHEST_TOKEN") {
+        Ok(token) if !token.is_empty() => return Ok(Some(token)),
+        Ok(_) | Err(std::env::VarError::NotPresent) => {}
+        Err(error) => return Err(error).context("failed to read IMGCHEST_TOKEN"),
+    }
+
+    match load_config_api_key().await {
+        Ok(token) => Ok(token),
+        Err(error) => {
+            eprintln!("Warning: failed to load the api-key from the config: {error:#}");
+            Ok(None)
+        }
+    }
+}
+
+/// Load the api key from the config.
+async fn load_config_api_key() -> anyhow::Result<Option<String>> {
+    let config_dir = crate::util::get_config_dir().await?;
+    let config_path = config_dir.join("config.toml");
+    let config_str = crate::util::read_or_init_user_config_str(&config_path).await?;
+    let user_config = UserConfig::new(&config_str)?;
+
+    user_config.get_api_key()
+}
@@ -24,11 +24,20 @@ impl UserConfig {
         Ok(())
     }
 
-    /*
-    pub fn get_api_key(&self) -> anyhow::Result<Option<()>> {
-        self.document.as_table().get()
+    /// Get the api key
+    pub fn get_api_key(&self) -> anyhow::Result<Option<String>> {
+        let api_key = match self.document.as_table().get("api-key") {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+
+        let api_key = api_key
+            .as_str()
+            .context("api-key key is not a string")?
+            .to_string();
+
+        Ok(Some(api_key))
     }
-    */
 
     /// Get cookies
     pub fn get_cookies(&self) -> anyhow::Result<Option<Vec<String>>> {
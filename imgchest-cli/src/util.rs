@@ -1,5 +1,7 @@
+use crate::UserConfig;
 use anyhow::Context;
 use directories_next::ProjectDirs;
+use std::cmp::Ordering;
 use std::path::Path;
 use std::path::PathBuf;
 use tokio::fs::File;
@@ -39,3 +41,83 @@ pub async fn read_or_init_user_config_str(config_path: &Path) -> anyhow::Result<
 
     Ok(config_str)
 }
+
+/// Load cookies from the user config into the client's cookie store.
+pub async fn load_cookies(client: &imgchest::Client) -> anyhow::Result<()> {
+    let config_dir = get_config_dir().await?;
+
+    let config_path = config_dir.join("config.toml");
+    let config_str = read_or_init_user_config_str(&config_path).await?;
+
+    let user_config = UserConfig::new(&config_str)?;
+
+    if let Some(cookies) = user_config.get_cookies()? {
+        let mut cookie_store = client
+            .get_cookie_store()
+            .lock()
+            .expect("cookie store is poisoned");
+
+        let url = client.base_url();
+        for cookie in cookies.iter() {
+            let cookie = imgchest::RawCookie::parse(cookie)
+                .with_context(|| format!("failed to parse cookie \"{cookie}\""))?;
+
+            cookie_store.insert_raw(&cookie, url)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare strings in natural order, so that "2.png" sorts before "10.png".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chunks = NaturalChunks(a);
+    let mut b_chunks = NaturalChunks(b);
+    loop {
+        let (a_chunk, b_chunk) = match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_chunk), Some(b_chunk)) => (a_chunk, b_chunk),
+        };
+
+        let is_a_digit = a_chunk.starts_with(|ch: char| ch.is_ascii_digit());
+        let is_b_digit = b_chunk.starts_with(|ch: char| ch.is_ascii_digit());
+        let ordering = if is_a_digit && is_b_digit {
+            let a_trimmed = a_chunk.trim_start_matches('0');
+            let b_trimmed = b_chunk.trim_start_matches('0');
+            a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed))
+        } else {
+            a_chunk
+                .chars()
+                .map(|ch| ch.to_ascii_lowercase())
+                .cmp(b_chunk.chars().map(|ch| ch.to_ascii_lowercase()))
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// An iterator over runs of digits and runs of non-digits in a string.
+struct NaturalChunks<'a>(&'a str);
+
+impl<'a> Iterator for NaturalChunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.0.chars().next()?;
+        let is_digit = first.is_ascii_digit();
+        let end = self
+            .0
+            .find(|ch: char| ch.is_ascii_digit() != is_digit)
+            .unwrap_or(self.0.len());
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
@@ -1,11 +1,27 @@
 mod builder;
+mod progress;
+mod ratelimit;
+mod retry;
+mod upload;
 
+pub use self::builder::ClientBuilder;
 pub use self::builder::CreatePostBuilder;
 pub use self::builder::ListPostsBuilder;
+pub use self::builder::NsfwFilter;
 pub use self::builder::SortOrder;
+pub use self::builder::Timeframe;
 pub use self::builder::UpdatePostBuilder;
 pub use self::builder::UploadPostFile;
+pub use self::progress::FileUploadProgress;
+pub use self::progress::UploadProgress;
+pub use self::ratelimit::NoopRateLimiter;
+pub use self::ratelimit::RateLimiter;
+pub use self::ratelimit::RatelimitStatus;
+pub use self::ratelimit::TokenBucketRateLimiter;
+pub use self::retry::RetryPolicy;
+pub use self::upload::UploadLimits;
 use crate::ApiCompletedResponse;
+use crate::ApiErrorResponse;
 use crate::ApiResponse;
 use crate::ApiUpdateFilesBulkRequest;
 use crate::Error;
@@ -13,28 +29,31 @@ use crate::FileUpdate;
 use crate::ListPostsPost;
 use crate::Post;
 use crate::PostFile;
+use crate::ScrapedComment;
 use crate::ScrapedPost;
 use crate::ScrapedUser;
 use crate::User;
-use jiff::RoundMode;
-use jiff::SignedDuration;
+use futures_util::Stream;
 use jiff::Timestamp;
-use jiff::TimestampRound;
-use jiff::Unit;
+use reqwest::header::HeaderMap;
 use reqwest::header::AUTHORIZATION;
+use reqwest::header::RETRY_AFTER;
 use reqwest::multipart::Form;
+use reqwest::StatusCode;
 use reqwest::Url;
-use reqwest_cookie_store::CookieStore;
 use reqwest_cookie_store::CookieStoreMutex;
 use scraper::Html;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::watch;
 
-// Should be 60, but that still triggers the ratelimit.
-// Add some leeway.
-const REQUESTS_PER_MINUTE: u8 = 40;
-const ONE_MINUTE: SignedDuration = SignedDuration::from_secs(60);
-const API_BASE: &str = "https://api.imgchest.com";
+const DEFAULT_API_BASE_URL: &str = "https://api.imgchest.com/";
+const DEFAULT_BASE_URL: &str = "https://imgchest.com/";
+
+/// The number of images uploaded per request by [`Client::create_post_batched`]
+/// if [`UploadLimits::max_images`] is not set.
+const DEFAULT_UPLOAD_BATCH_SIZE: usize = 20;
 
 fn bool_to_str(b: bool) -> &'static str {
     if b {
@@ -44,91 +63,150 @@ fn bool_to_str(b: bool) -> &'static str {
     }
 }
 
-fn minute_trunc_round_config() -> TimestampRound {
-    TimestampRound::new()
-        .smallest(Unit::Minute)
-        .mode(RoundMode::Trunc)
+/// Convert a length to a u64.
+fn usize_to_u64(n: usize) -> u64 {
+    // A usize always fits in a u64.
+    u64::try_from(n).unwrap()
 }
 
-#[derive(Debug)]
-struct RatelimitState {
-    last_refreshed: Timestamp,
-    remaining_requests: u8,
+/// Make a url by appending path segments to a base url.
+///
+/// The base url must be able to be a base.
+fn make_url(base: &Url, segments: &[&str]) -> Url {
+    let mut url = base.clone();
+    url.path_segments_mut()
+        .expect("url cannot be a base")
+        .pop_if_empty()
+        .extend(segments);
+    url
 }
 
-impl RatelimitState {
-    fn new() -> Self {
-        let last_refreshed = Timestamp::now()
-            .round(minute_trunc_round_config())
-            .expect("invalid round config");
+/// Convert an error response into an error.
+///
+/// Successful responses are returned as-is.
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
 
-        Self {
-            last_refreshed,
-            remaining_requests: REQUESTS_PER_MINUTE,
-        }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+
+    // Error bodies are not always json, like for scraping-based functions.
+    let body = response.bytes().await.unwrap_or_default();
+    let body: Option<ApiErrorResponse> = serde_json::from_slice(&body).ok();
+    let (message, field_errors) = match body {
+        Some(body) => (body.message, body.errors),
+        None => (None, None),
+    };
+
+    let error = match status {
+        StatusCode::UNAUTHORIZED => Error::Unauthorized { message },
+        StatusCode::FORBIDDEN => Error::Forbidden { message },
+        StatusCode::NOT_FOUND => Error::NotFound { message },
+        StatusCode::UNPROCESSABLE_ENTITY => Error::Validation {
+            message,
+            field_errors: field_errors.unwrap_or_default(),
+        },
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited { retry_after },
+        status => Error::Server { status, message },
+    };
+
+    Err(error)
+}
+
+/// Parse the value of a Retry-After header.
+///
+/// This is either a number of seconds or an http date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
     }
-    /// Get the time needed to sleep to respect the ratelimit.
-    ///
-    /// # Returns
-    /// Returns `None` is a request can be made.
-    /// Otherwise, returns the time needed to sleep before calling this again.
-    fn get_sleep_duration(&mut self) -> Option<Duration> {
-        let now = Timestamp::now()
-            .round(minute_trunc_round_config())
-            .expect("invalid round config");
-
-        // Refresh the number of requests each minute.
-        if self.last_refreshed.duration_until(now) >= ONE_MINUTE {
-            self.last_refreshed = now;
-            self.remaining_requests = REQUESTS_PER_MINUTE;
-        }
 
-        // If we are allowed to make a request now, make it.
-        if self.remaining_requests > 0 {
-            self.remaining_requests -= 1;
-            return None;
-        }
+    let date = jiff::fmt::rfc2822::parse(value).ok()?;
+    let duration = Timestamp::now().duration_until(date.timestamp());
 
-        // Otherwise, sleep until the next refresh and try again.
-        let duration = ONE_MINUTE.saturating_sub(self.last_refreshed.duration_until(now));
-        let duration = Duration::try_from(duration).unwrap_or(Duration::ZERO);
+    Some(Duration::try_from(duration).unwrap_or(Duration::ZERO))
+}
 
-        Some(duration)
-    }
+/// How a request should be sent.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum RequestKind {
+    /// A scraping-based or undocumented request to the website.
+    Site,
+
+    /// An idempotent request to the v1 API.
+    Api,
+
+    /// A request to the v1 API that is not idempotent.
+    ApiNonIdempotent,
 }
 
 #[derive(Debug)]
 struct ClientState {
     token: std::sync::RwLock<Option<Arc<str>>>,
-    ratelimit_state: std::sync::Mutex<RatelimitState>,
+    rate_limiter: Arc<dyn RateLimiter>,
+    ratelimit_site_requests: bool,
+    ratelimit_status: std::sync::Mutex<Option<RatelimitStatus>>,
 
     cookie_store: Arc<CookieStoreMutex>,
+
+    api_base_url: Url,
+    base_url: Url,
+
+    retry_policy: Option<RetryPolicy>,
+
+    upload_limits: UploadLimits,
 }
 
 impl ClientState {
-    fn new() -> Self {
-        let token = std::sync::RwLock::new(None);
-        let ratelimit_state = std::sync::Mutex::new(RatelimitState::new());
-
-        let cookie_store = CookieStore::new();
-        let cookie_store = CookieStoreMutex::new(cookie_store);
-        let cookie_store = Arc::new(cookie_store);
+    fn new(builder: &ClientBuilder, cookie_store: Arc<CookieStoreMutex>) -> Self {
+        let token = std::sync::RwLock::new(builder.token.as_deref().map(Arc::from));
+        let rate_limiter = builder
+            .rate_limiter
+            .clone()
+            .unwrap_or_else(|| Arc::new(TokenBucketRateLimiter::default()));
+        let ratelimit_status = std::sync::Mutex::new(None);
 
         Self {
             token,
-            ratelimit_state,
+            rate_limiter,
+            ratelimit_site_requests: builder.ratelimit_site_requests,
+            ratelimit_status,
 
             cookie_store,
+
+            api_base_url: builder.api_base_url.clone(),
+            base_url: builder.base_url.clone(),
+
+            retry_policy: builder.retry_policy.clone(),
+
+            upload_limits: builder.upload_limits.clone(),
         }
     }
 
+    /// Update the ratelimit status from response headers.
+    fn update_ratelimit_status(&self, headers: &HeaderMap) {
+        let status = match RatelimitStatus::from_headers(headers) {
+            Some(status) => status,
+            None => return,
+        };
+
+        self.rate_limiter.update(&status);
+
+        *self
+            .ratelimit_status
+            .lock()
+            .unwrap_or_else(|error| error.into_inner()) = Some(status);
+    }
+
     async fn ratelimit(&self) {
         loop {
-            let maybe_sleep_duration = self
-                .ratelimit_state
-                .lock()
-                .expect("ratelimit state mutex poisoned")
-                .get_sleep_duration();
+            let maybe_sleep_duration = self.rate_limiter.try_acquire();
             match maybe_sleep_duration {
                 Some(sleep_duration) => {
                     tokio::time::sleep(sleep_duration).await;
@@ -151,15 +229,172 @@ pub struct Client {
 
 impl Client {
     /// Make a new client
+    ///
+    /// # Panics
+    /// Panics if the http client could not be built.
+    /// Use [`Client::builder`] to handle this error.
     pub fn new() -> Self {
-        let state = Arc::new(ClientState::new());
-
-        let client = reqwest::Client::builder()
-            .cookie_provider(state.cookie_store.clone())
+        ClientBuilder::new()
             .build()
-            .expect("failed to build client");
+            .expect("failed to build client")
+    }
+
+    /// Make a new builder for a client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Get the base url of the v1 API.
+    pub fn api_base_url(&self) -> &Url {
+        &self.state.api_base_url
+    }
 
-        Self { client, state }
+    /// Get the base url of the website.
+    pub fn base_url(&self) -> &Url {
+        &self.state.base_url
+    }
+
+    /// Make a url for the v1 API from path segments.
+    fn api_url(&self, segments: &[&str]) -> Url {
+        make_url(&self.state.api_base_url, segments)
+    }
+
+    /// Make a url for the website from path segments.
+    fn site_url(&self, segments: &[&str]) -> Url {
+        make_url(&self.state.base_url, segments)
+    }
+
+    /// Get the retry policy for a kind of request.
+    ///
+    /// Returns `None` if this kind of request should not be retried.
+    fn get_retry_policy(&self, kind: RequestKind) -> Option<&RetryPolicy> {
+        self.state
+            .retry_policy
+            .as_ref()
+            .filter(|policy| kind != RequestKind::ApiNonIdempotent || policy.retry_non_idempotent)
+    }
+
+    /// Send a request, retrying it according to the retry policy.
+    ///
+    /// Error statuses are converted into errors.
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        kind: RequestKind,
+    ) -> Result<reqwest::Response, Error> {
+        let can_retry = self.get_retry_policy(kind).is_some();
+        let mut request = Some(request);
+        self.send_with(kind, || {
+            let current = request.take()?;
+
+            // Requests with streaming bodies cannot be cloned, and therefore cannot be retried.
+            if can_retry {
+                request = current.try_clone();
+            }
+
+            Some(current)
+        })
+        .await
+    }
+
+    /// Send a multipart upload request, retrying it according to the retry policy.
+    ///
+    /// The request can only be retried if none of the files are streams.
+    async fn send_upload<F>(
+        &self,
+        images: Vec<UploadPostFile>,
+        progress: Option<watch::Sender<UploadProgress>>,
+        make_request: F,
+    ) -> Result<reqwest::Response, Error>
+    where
+        F: Fn(Form) -> reqwest::RequestBuilder,
+    {
+        let kind = RequestKind::ApiNonIdempotent;
+        let can_retry = self.get_retry_policy(kind).is_some();
+        let progress = progress.map(Arc::new);
+        let mut images = Some(images);
+        self.send_with(kind, || {
+            let current = images.take()?;
+
+            if can_retry {
+                images = current.iter().map(UploadPostFile::try_clone).collect();
+            }
+
+            if let Some(progress) = progress.as_ref() {
+                self::progress::reset_progress(progress, &current);
+            }
+
+            let mut form = Form::new();
+            for (index, file) in current.into_iter().enumerate() {
+                let part = match progress.as_ref() {
+                    Some(progress) => self::progress::tracked_part(file, index, progress.clone()),
+                    None => file.into_part(),
+                };
+                form = form.part("images[]", part);
+            }
+
+            Some(make_request(form))
+        })
+        .await
+    }
+
+    /// Send requests made by the given function, retrying them according to the retry policy.
+    ///
+    /// The function is called once per attempt,
+    /// and should return `None` if the request cannot be made again.
+    /// Error statuses are converted into errors.
+    async fn send_with<F>(
+        &self,
+        kind: RequestKind,
+        mut make_request: F,
+    ) -> Result<reqwest::Response, Error>
+    where
+        F: FnMut() -> Option<reqwest::RequestBuilder>,
+    {
+        let retry_policy = self.get_retry_policy(kind);
+        let mut request = make_request().expect("missing first request");
+
+        let use_rate_limiter = kind != RequestKind::Site || self.state.ratelimit_site_requests;
+
+        let mut attempt = 1;
+        loop {
+            if use_rate_limiter {
+                self.state.ratelimit().await;
+            }
+
+            let result = match request.send().await {
+                Ok(response) => {
+                    if use_rate_limiter {
+                        self.state.update_ratelimit_status(response.headers());
+                    }
+
+                    error_for_status(response).await
+                }
+                Err(error) => Err(Error::from(error)),
+            };
+            let error = match result {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+
+            let policy = match retry_policy {
+                Some(policy) if attempt < policy.max_attempts && policy.is_retryable(&error) => {
+                    policy
+                }
+                _ => return Err(error),
+            };
+            let delay = match policy.get_delay(attempt, &error) {
+                Some(delay) => delay,
+                None => return Err(error),
+            };
+            request = match make_request() {
+                Some(request) => request,
+                None => return Err(error),
+            };
+            tokio::time::sleep(delay).await;
+
+            attempt += 1;
+        }
     }
 
     /// Scrape a post from a post id.
@@ -170,15 +405,10 @@ impl Client {
     /// # Warning
     /// This is a scraping-based function.
     pub async fn get_scraped_post(&self, id: &str) -> Result<ScrapedPost, Error> {
-        let url = format!("https://imgchest.com/p/{id}");
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+        let url = self.site_url(&["p", id]);
+        let request = self.client.get(url);
+        let response = self.send(request, RequestKind::Site).await?;
+        let text = response.text().await?;
 
         let post = tokio::task::spawn_blocking(move || {
             let html = Html::parse_document(text.as_str());
@@ -189,6 +419,21 @@ impl Client {
         Ok(post)
     }
 
+    /// Scrape the comments of a post from a post id.
+    ///
+    /// Comments are threaded, so only top-level comments are returned.
+    /// Replies can be found in [`ScrapedComment::replies`].
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    ///
+    /// # Warning
+    /// This is a scraping-based function.
+    pub async fn get_scraped_comments(&self, id: &str) -> Result<Vec<ScrapedComment>, Error> {
+        let post = self.get_scraped_post(id).await?;
+        Ok(post.comments.into_vec())
+    }
+
     /// Scrape a user from a username.
     ///
     /// # Authorization
@@ -197,15 +442,10 @@ impl Client {
     /// # Warning
     /// This is a scraping-based function.
     pub async fn get_scraped_user(&self, name: &str) -> Result<ScrapedUser, Error> {
-        let url = format!("https://imgchest.com/u/{name}");
-        let text = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .error_for_status()?
-            .text()
-            .await?;
+        let url = self.site_url(&["u", name]);
+        let request = self.client.get(url);
+        let response = self.send(request, RequestKind::Site).await?;
+        let text = response.text().await?;
 
         let user = tokio::task::spawn_blocking(move || {
             let html = Html::parse_document(text.as_str());
@@ -224,7 +464,7 @@ impl Client {
     /// # Warning
     /// This api call is undocumented.
     pub async fn list_posts(&self, builder: ListPostsBuilder) -> Result<Vec<ListPostsPost>, Error> {
-        let mut url = Url::parse("https://imgchest.com/api/posts").unwrap();
+        let mut url = self.site_url(&["api", "posts"]);
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -232,6 +472,7 @@ impl Client {
                 SortOrder::Popular => "popular",
                 SortOrder::New => "new",
                 SortOrder::Old => "old",
+                SortOrder::Top => "top",
             };
             query_pairs.append_pair("sort", sort_str);
 
@@ -244,15 +485,75 @@ impl Client {
             if builder.profile {
                 query_pairs.append_pair("profile", "true");
             }
+
+            if let Some(search) = builder.search.as_deref() {
+                query_pairs.append_pair("search", search);
+            }
+
+            if let Some(nsfw) = builder.nsfw {
+                let nsfw_str = match nsfw {
+                    NsfwFilter::Include => "true",
+                    NsfwFilter::Only => "only",
+                    NsfwFilter::Exclude => "false",
+                };
+                query_pairs.append_pair("nsfw", nsfw_str);
+            }
+
+            if let Some(timeframe) = builder.timeframe {
+                let timeframe_str = match timeframe {
+                    Timeframe::Day => "day",
+                    Timeframe::Week => "week",
+                    Timeframe::Month => "month",
+                    Timeframe::Year => "year",
+                    Timeframe::All => "all",
+                };
+                query_pairs.append_pair("time", timeframe_str);
+            }
+
+            if let Some(favorites) = builder.favorites.as_deref() {
+                query_pairs.append_pair("favorites", favorites);
+            }
         }
 
-        let response = self.client.get(url.as_str()).send().await?;
+        let request = self.client.get(url);
+        let response = self.send(request, RequestKind::Site).await?;
 
-        let posts: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let posts: ApiResponse<_> = response.json().await?;
 
         Ok(posts.data)
     }
 
+    /// List posts from various sources, walking through all pages lazily.
+    ///
+    /// This starts at the page of the builder, and stops at the first empty page,
+    /// at the first error, or once the builder's max items or max pages are reached.
+    /// Posts that shift between pages while listing are only returned once.
+    ///
+    /// # Authorization
+    /// This function does NOT require the use of a token.
+    ///
+    /// # Warning
+    /// This api call is undocumented.
+    pub fn list_posts_stream(
+        &self,
+        builder: ListPostsBuilder,
+    ) -> impl Stream<Item = Result<ListPostsPost, Error>> + Send + 'static {
+        let state = ListPostsStreamState {
+            client: self.clone(),
+            builder,
+            posts: Vec::new().into_iter(),
+            seen: HashSet::new(),
+            num_items: 0,
+            num_pages: 0,
+            done: false,
+        };
+
+        futures_util::stream::unfold(state, |mut state| async move {
+            let result = state.next().await?;
+            Some((result, state))
+        })
+    }
+
     /// Set the token to use for future requests.
     ///
     /// This allows the use of functions that require authorization.
@@ -276,6 +577,17 @@ impl Client {
             .clone()
     }
 
+    /// Get the most recent ratelimit status reported by the server.
+    ///
+    /// Returns `None` if the server has not reported a ratelimit status yet.
+    pub fn ratelimit_status(&self) -> Option<RatelimitStatus> {
+        *self
+            .state
+            .ratelimit_status
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
     /// Get the cookie store.
     pub fn get_cookie_store(&self) -> &Arc<CookieStoreMutex> {
         &self.state.cookie_store
@@ -287,75 +599,214 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn get_post(&self, id: &str) -> Result<Post, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}");
+        let url = self.api_url(&["v1", "post", id]);
 
-        self.state.ratelimit().await;
-
-        let response = self
+        let request = self
             .client
             .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let post: ApiResponse<_> = response.json().await?;
 
         Ok(post.data)
     }
 
     /// Create a post.
     ///
+    /// If any files have descriptions, they are set with [`Client::update_files_bulk`] after uploading.
+    /// If that fails, [`Error::FileDescriptions`] is returned with the created post.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn create_post(&self, data: CreatePostBuilder) -> Result<Post, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post");
+        let url = self.api_url(&["v1", "post"]);
 
-        let mut form = Form::new();
+        let mut fields = Vec::new();
 
         if let Some(title) = data.title {
             if title.len() < 3 {
                 return Err(Error::TitleTooShort);
             }
 
-            form = form.text("title", title);
+            fields.push(("title", title));
         }
 
         if let Some(privacy) = data.privacy {
-            form = form.text("privacy", privacy.as_str());
+            fields.push(("privacy", privacy.as_str().to_string()));
         }
 
         if let Some(anonymous) = data.anonymous {
-            form = form.text("anonymous", bool_to_str(anonymous));
+            fields.push(("anonymous", bool_to_str(anonymous).to_string()));
         }
 
         if let Some(nsfw) = data.nsfw {
-            form = form.text("nsfw", bool_to_str(nsfw));
+            fields.push(("nsfw", bool_to_str(nsfw).to_string()));
         }
 
         if data.images.is_empty() {
             return Err(Error::MissingImages);
         }
+        self.state.upload_limits.validate(&data.images)?;
+        let uploads = upload_descriptions(&data.images);
 
-        for file in data.images {
-            let part = reqwest::multipart::Part::stream(file.body).file_name(file.file_name);
+        let response = self
+            .send_upload(data.images, data.progress, |mut form| {
+                for (name, value) in fields.iter() {
+                    form = form.text(*name, value.clone());
+                }
+
+                self.client
+                    .post(url.clone())
+                    .header(AUTHORIZATION, format!("Bearer {token}"))
+                    .multipart(form)
+            })
+            .await?;
 
-            form = form.part("images[]", part);
+        let post: ApiResponse<_> = response.json().await?;
+
+        self.apply_descriptions(post.data, uploads).await
+    }
+
+    /// Create a post, splitting the images into batches.
+    ///
+    /// The post is created with the first batch of images,
+    /// and the rest are added in order with [`Client::add_post_images`].
+    /// Batches are the size of [`UploadLimits::max_images`], or 20 images if that is not set.
+    /// Every file is checked against the upload limits before anything is sent.
+    ///
+    /// Errors from before the post is created are returned as they are.
+    /// If a batch fails after the post is created, [`Error::BatchUpload`] is returned with the id of the post
+    /// and the number of images that were uploaded, so that the upload can be resumed.
+    /// Upload progress is reset for each batch.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn create_post_batched(&self, mut data: CreatePostBuilder) -> Result<Post, Error> {
+        self.get_token().ok_or(Error::MissingToken)?;
+        if data.title.as_ref().is_some_and(|title| title.len() < 3) {
+            return Err(Error::TitleTooShort);
+        }
+        if data.images.is_empty() {
+            return Err(Error::MissingImages);
         }
 
-        self.state.ratelimit().await;
+        let batch_size = self.upload_batch_size();
+        for batch in data.images.chunks(batch_size) {
+            self.state.upload_limits.validate(batch)?;
+        }
 
-        let response = self
-            .client
-            .post(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .multipart(form)
-            .send()
-            .await?;
+        let progress = data.progress.clone();
+        let rest = data
+            .images
+            .split_off(std::cmp::min(batch_size, data.images.len()));
+        let uploaded = data.images.len();
+
+        let mut post = self.create_post(data).await.map_err(|error| match error {
+            // The post was created if only the descriptions failed.
+            Error::FileDescriptions { ref post, .. } => Error::BatchUpload {
+                post_id: Some(post.id.clone()),
+                batch: 0,
+                uploaded,
+                error: Box::new(error),
+            },
+            error => error,
+        })?;
+
+        if let Some(new_post) = self
+            .add_post_image_batches(&post.id, rest, progress, 1, uploaded)
+            .await?
+        {
+            post = new_post;
+        }
+
+        Ok(post)
+    }
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+    /// Add images to a post, splitting them into batches.
+    ///
+    /// Batches are the size of [`UploadLimits::max_images`], or 20 images if that is not set.
+    /// Every file is checked against the upload limits before anything is sent.
+    ///
+    /// If a batch fails, [`Error::BatchUpload`] is returned with the number of images that were uploaded,
+    /// so that the upload can be resumed.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn add_post_images_batched<I>(&self, id: &str, images: I) -> Result<Post, Error>
+    where
+        I: IntoIterator<Item = UploadPostFile>,
+    {
+        self.get_token().ok_or(Error::MissingToken)?;
+        let images: Vec<_> = images.into_iter().collect();
+        if images.is_empty() {
+            return Err(Error::MissingImages);
+        }
 
-        Ok(post.data)
+        for batch in images.chunks(self.upload_batch_size()) {
+            self.state.upload_limits.validate(batch)?;
+        }
+
+        let post = self
+            .add_post_image_batches(id, images, None, 0, 0)
+            .await?
+            .expect("there should be at least 1 batch");
+
+        Ok(post)
+    }
+
+    /// Get the number of images to upload in each batch.
+    fn upload_batch_size(&self) -> usize {
+        self.state
+            .upload_limits
+            .max_images
+            .unwrap_or(DEFAULT_UPLOAD_BATCH_SIZE)
+            .max(1)
+    }
+
+    /// Add images to a post in batches, numbering them from the given batch.
+    ///
+    /// `uploaded` is the number of images of the upload that were already uploaded.
+    /// Returns the post after the last batch, or `None` if there were no images.
+    async fn add_post_image_batches(
+        &self,
+        id: &str,
+        mut images: Vec<UploadPostFile>,
+        progress: Option<watch::Sender<UploadProgress>>,
+        mut batch: usize,
+        mut uploaded: usize,
+    ) -> Result<Option<Post>, Error> {
+        let batch_size = self.upload_batch_size();
+        let mut post = None;
+        while !images.is_empty() {
+            let next = images.split_off(std::cmp::min(batch_size, images.len()));
+            let batch_images = std::mem::replace(&mut images, next);
+            let num_images = batch_images.len();
+
+            let new_post = self
+                .add_post_images_inner(id, batch_images, progress.clone())
+                .await
+                .map_err(|error| {
+                    // The images were uploaded if only the descriptions failed.
+                    let uploaded = match &error {
+                        Error::FileDescriptions { .. } => uploaded + num_images,
+                        _ => uploaded,
+                    };
+                    Error::BatchUpload {
+                        post_id: Some(id.into()),
+                        batch,
+                        uploaded,
+                        error: Box::new(error),
+                    }
+                })?;
+            post = Some(new_post);
+
+            uploaded += num_images;
+            batch += 1;
+        }
+
+        Ok(post)
     }
 
     /// Update a post.
@@ -364,7 +815,7 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn update_post(&self, id: &str, data: UpdatePostBuilder) -> Result<Post, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}");
+        let url = self.api_url(&["v1", "post", id]);
 
         let mut form = Vec::new();
 
@@ -384,20 +835,17 @@ impl Client {
             form.push(("nsfw", bool_to_str(nsfw)));
         }
 
-        self.state.ratelimit().await;
-
         // Not using a multipart form here is intended.
         // Even though we use a multipart form for creating a post,
         // the server will silently ignore requests that aren't form-urlencoded.
-        let response = self
+        let request = self
             .client
             .patch(url)
             .header(AUTHORIZATION, format!("Bearer {token}"))
-            .form(&form)
-            .send()
-            .await?;
+            .form(&form);
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let post: ApiResponse<_> = response.json().await?;
 
         Ok(post.data)
     }
@@ -408,18 +856,15 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn delete_post(&self, id: &str) -> Result<(), Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}");
+        let url = self.api_url(&["v1", "post", id]);
 
-        self.state.ratelimit().await;
-
-        let response = self
+        let request = self
             .client
             .delete(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = response.json().await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -437,18 +882,15 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn favorite_post(&self, id: &str) -> Result<bool, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}/favorite");
+        let url = self.api_url(&["v1", "post", id, "favorite"]);
 
-        self.state.ratelimit().await;
-
-        let response = self
+        let request = self
             .client
             .post(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::ApiNonIdempotent).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = response.json().await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -463,42 +905,97 @@ impl Client {
 
     /// Add images to a post.
     ///
+    /// If any files have descriptions, they are set with [`Client::update_files_bulk`] after uploading.
+    /// If that fails, [`Error::FileDescriptions`] is returned with the updated post.
+    ///
     /// # Authorization
     /// This function REQUIRES a token.
     pub async fn add_post_images<I>(&self, id: &str, images: I) -> Result<Post, Error>
     where
         I: IntoIterator<Item = UploadPostFile>,
     {
-        let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/post/{id}/add");
-
-        let mut form = Form::new();
+        self.add_post_images_inner(id, images, None).await
+    }
 
-        let mut num_images = 0;
-        for file in images {
-            let part = reqwest::multipart::Part::stream(file.body).file_name(file.file_name);
+    /// Add images to a post, reporting upload progress to the given channel.
+    ///
+    /// Progress is reset to zero if the upload is retried.
+    ///
+    /// # Authorization
+    /// This function REQUIRES a token.
+    pub async fn add_post_images_with_progress<I>(
+        &self,
+        id: &str,
+        images: I,
+        progress: watch::Sender<UploadProgress>,
+    ) -> Result<Post, Error>
+    where
+        I: IntoIterator<Item = UploadPostFile>,
+    {
+        self.add_post_images_inner(id, images, Some(progress)).await
+    }
 
-            form = form.part("images[]", part);
-            num_images += 1;
-        }
+    async fn add_post_images_inner<I>(
+        &self,
+        id: &str,
+        images: I,
+        progress: Option<watch::Sender<UploadProgress>>,
+    ) -> Result<Post, Error>
+    where
+        I: IntoIterator<Item = UploadPostFile>,
+    {
+        let token = self.get_token().ok_or(Error::MissingToken)?;
+        let url = self.api_url(&["v1", "post", id, "add"]);
 
-        if num_images == 0 {
+        let images: Vec<_> = images.into_iter().collect();
+        if images.is_empty() {
             return Err(Error::MissingImages);
         }
-
-        self.state.ratelimit().await;
+        self.state.upload_limits.validate(&images)?;
+        let uploads = upload_descriptions(&images);
 
         let response = self
-            .client
-            .post(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .multipart(form)
-            .send()
+            .send_upload(images, progress, |form| {
+                self.client
+                    .post(url.clone())
+                    .header(AUTHORIZATION, format!("Bearer {token}"))
+                    .multipart(form)
+            })
             .await?;
 
-        let post: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let post: ApiResponse<_> = response.json().await?;
 
-        Ok(post.data)
+        self.apply_descriptions(post.data, uploads).await
+    }
+
+    /// Set the descriptions of newly uploaded files.
+    ///
+    /// The uploaded files must be the last files of the post.
+    async fn apply_descriptions(
+        &self,
+        mut post: Post,
+        uploads: Vec<(String, Option<String>)>,
+    ) -> Result<Post, Error> {
+        let updates = match_uploaded_files(&post, &uploads);
+        if updates.is_empty() {
+            return Ok(post);
+        }
+
+        match self.update_files_bulk(updates).await {
+            Ok(files) => {
+                for file in files {
+                    if let Some(image) = post.images.iter_mut().find(|image| image.id == file.id) {
+                        *image = file;
+                    }
+                }
+
+                Ok(post)
+            }
+            Err(error) => Err(Error::FileDescriptions {
+                post: Box::new(post),
+                error: Box::new(error),
+            }),
+        }
     }
 
     /// Get a user by username.
@@ -507,18 +1004,15 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn get_user(&self, username: &str) -> Result<User, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/user/{username}");
-
-        self.state.ratelimit().await;
+        let url = self.api_url(&["v1", "user", username]);
 
-        let response = self
+        let request = self
             .client
             .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let user: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let user: ApiResponse<_> = response.json().await?;
 
         Ok(user.data)
     }
@@ -534,18 +1028,15 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn get_file(&self, id: &str) -> Result<PostFile, Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/file/{id}");
-
-        self.state.ratelimit().await;
+        let url = self.api_url(&["v1", "file", id]);
 
-        let response = self
+        let request = self
             .client
             .get(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let file: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let file: ApiResponse<_> = response.json().await?;
 
         Ok(file.data)
     }
@@ -556,23 +1047,20 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn update_file(&self, id: &str, description: &str) -> Result<(), Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/file/{id}");
+        let url = self.api_url(&["v1", "file", id]);
 
         if description.is_empty() {
             return Err(Error::MissingDescription);
         }
 
-        self.state.ratelimit().await;
-
-        let response = self
+        let request = self
             .client
             .patch(url)
             .form(&[("description", description)])
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = response.json().await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -586,18 +1074,15 @@ impl Client {
     /// This function REQUIRES a token.
     pub async fn delete_file(&self, id: &str) -> Result<(), Error> {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/file/{id}");
-
-        self.state.ratelimit().await;
+        let url = self.api_url(&["v1", "file", id]);
 
-        let response = self
+        let request = self
             .client
             .delete(url)
-            .header(AUTHORIZATION, format!("Bearer {token}"))
-            .send()
-            .await?;
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let response: ApiCompletedResponse = response.error_for_status()?.json().await?;
+        let response: ApiCompletedResponse = response.json().await?;
         if !response.success {
             return Err(Error::ApiOperationFailed);
         }
@@ -611,7 +1096,7 @@ impl Client {
         I: IntoIterator<Item = FileUpdate>,
     {
         let token = self.get_token().ok_or(Error::MissingToken)?;
-        let url = format!("{API_BASE}/v1/files");
+        let url = self.api_url(&["v1", "files"]);
 
         let data = files
             .into_iter()
@@ -624,22 +1109,134 @@ impl Client {
             .collect::<Result<Vec<_>, _>>()?;
         let data = ApiUpdateFilesBulkRequest { data };
 
-        self.state.ratelimit().await;
-
-        let response = self
+        let request = self
             .client
             .patch(url)
             .header(AUTHORIZATION, format!("Bearer {token}"))
-            .json(&data)
-            .send()
-            .await?;
+            .json(&data);
+        let response = self.send(request, RequestKind::Api).await?;
 
-        let file: ApiResponse<_> = response.error_for_status()?.json().await?;
+        let file: ApiResponse<_> = response.json().await?;
 
         Ok(file.data)
     }
 }
 
+/// Get the file names and descriptions of files to upload, in upload order.
+fn upload_descriptions(images: &[UploadPostFile]) -> Vec<(String, Option<String>)> {
+    images
+        .iter()
+        .map(|file| {
+            let description = file
+                .description
+                .clone()
+                .filter(|description| !description.is_empty());
+            (file.file_name.clone(), description)
+        })
+        .collect()
+}
+
+/// Match uploaded files to the files of the post they were uploaded to,
+/// returning the updates needed to set their descriptions.
+///
+/// Uploaded files are matched to the last files of the post by original name if it is known.
+/// Files that could not be matched by name take the next unused file in position order.
+fn match_uploaded_files(post: &Post, uploads: &[(String, Option<String>)]) -> Vec<FileUpdate> {
+    let mut files: Vec<&PostFile> = post.images.iter().collect();
+    files.sort_by_key(|file| file.position);
+    let new_files = &files[files.len().saturating_sub(uploads.len())..];
+
+    let mut used = vec![false; new_files.len()];
+    let mut matches: Vec<Option<usize>> = uploads
+        .iter()
+        .map(|(file_name, _description)| {
+            let matched = new_files.iter().zip(used.iter()).position(|(file, used)| {
+                !used && file.original_name.as_deref() == Some(file_name.as_str())
+            })?;
+            used[matched] = true;
+            Some(matched)
+        })
+        .collect();
+
+    let mut unused = (0..new_files.len()).filter(|&index| !used[index]);
+    for matched in matches.iter_mut().filter(|matched| matched.is_none()) {
+        *matched = unused.next();
+    }
+
+    uploads
+        .iter()
+        .zip(matches)
+        .filter_map(|((_file_name, description), matched)| {
+            Some(FileUpdate {
+                id: new_files[matched?].id.to_string(),
+                description: description.clone()?,
+            })
+        })
+        .collect()
+}
+
+/// The state of a list posts stream.
+struct ListPostsStreamState {
+    client: Client,
+    builder: ListPostsBuilder,
+    posts: std::vec::IntoIter<ListPostsPost>,
+    seen: HashSet<Box<str>>,
+    num_items: u64,
+    num_pages: u64,
+    done: bool,
+}
+
+impl ListPostsStreamState {
+    /// Get the next post.
+    async fn next(&mut self) -> Option<Result<ListPostsPost, Error>> {
+        loop {
+            if self
+                .builder
+                .max_items
+                .is_some_and(|max_items| self.num_items >= max_items)
+            {
+                return None;
+            }
+
+            if let Some(post) = self.posts.next() {
+                if !self.seen.insert(post.id.clone()) {
+                    continue;
+                }
+
+                self.num_items += 1;
+                return Some(Ok(post));
+            }
+
+            if self.done
+                || self
+                    .builder
+                    .max_pages
+                    .is_some_and(|max_pages| self.num_pages >= max_pages)
+            {
+                return None;
+            }
+
+            let posts = match self.client.list_posts(self.builder.clone()).await {
+                Ok(posts) => posts,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            };
+            // A page with only seen posts means the listing stopped advancing,
+            // so stop instead of requesting the same page forever.
+            if posts.iter().all(|post| self.seen.contains(&post.id)) {
+                self.done = true;
+                return None;
+            }
+
+            self.posts = posts.into_iter();
+            self.builder.page += 1;
+            self.num_pages += 1;
+        }
+    }
+}
+
 impl Default for Client {
     fn default() -> Self {
         Self::new()
@@ -1,8 +1,280 @@
+use super::upload::sniff_content_type;
+use super::upload::SNIFF_LEN;
+use super::usize_to_u64;
+use super::ClientState;
+use super::RateLimiter;
+use super::RetryPolicy;
+use super::UploadLimits;
+use super::UploadProgress;
+use super::DEFAULT_API_BASE_URL;
+use super::DEFAULT_BASE_URL;
+use crate::Client;
+use crate::Error;
 use crate::PostPrivacy;
+use futures_util::TryStreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+use reqwest::multipart::Part;
+use reqwest::Proxy;
+use reqwest::Url;
+use reqwest_cookie_store::CookieStore;
+use reqwest_cookie_store::CookieStoreMutex;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::sync::watch;
 use tokio_util::codec::BytesCodec;
 use tokio_util::codec::FramedRead;
 
+/// A builder for a client.
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    /// The base url of the v1 API.
+    ///
+    /// Defaults to `https://api.imgchest.com/`.
+    pub api_base_url: Url,
+
+    /// The base url of the website.
+    ///
+    /// This is used for scraping-based functions and undocumented api calls.
+    /// Defaults to `https://imgchest.com/`.
+    pub base_url: Url,
+
+    /// The timeout for an entire request.
+    pub timeout: Option<Duration>,
+
+    /// The timeout for connecting to a server.
+    pub connect_timeout: Option<Duration>,
+
+    /// The user agent.
+    pub user_agent: Option<String>,
+
+    /// The proxies to use.
+    pub proxies: Vec<Proxy>,
+
+    /// Headers to send with every request.
+    pub default_headers: HeaderMap,
+
+    /// The initial token.
+    pub token: Option<String>,
+
+    /// The cookie store.
+    ///
+    /// If not specified, a new empty cookie store is created.
+    pub cookie_store: Option<Arc<CookieStoreMutex>>,
+
+    /// A preconfigured http client.
+    ///
+    /// If specified, the timeouts, user agent, proxies, and default headers are ignored.
+    /// The cookie store is also not attached to this client,
+    /// so it should be built with [`reqwest::ClientBuilder::cookie_provider`]
+    /// using the same store passed to [`ClientBuilder::shared_cookie_store`] if cookies are needed.
+    pub client: Option<reqwest::Client>,
+
+    /// The policy for retrying failed requests.
+    ///
+    /// If not specified, requests are not retried.
+    pub retry_policy: Option<RetryPolicy>,
+
+    /// The ratelimiter.
+    ///
+    /// If not specified, a [`TokenBucketRateLimiter`](crate::TokenBucketRateLimiter) allowing 40 requests per minute is used.
+    pub rate_limiter: Option<Arc<dyn RateLimiter>>,
+
+    /// Whether to apply the ratelimiter to scraping-based functions and undocumented api calls.
+    ///
+    /// Defaults to false.
+    pub ratelimit_site_requests: bool,
+
+    /// Limits checked before uploading files.
+    ///
+    /// Defaults to no limits.
+    pub upload_limits: UploadLimits,
+}
+
+impl ClientBuilder {
+    /// Create a new builder.
+    pub fn new() -> Self {
+        Self {
+            api_base_url: Url::parse(DEFAULT_API_BASE_URL).unwrap(),
+            base_url: Url::parse(DEFAULT_BASE_URL).unwrap(),
+            timeout: None,
+            connect_timeout: None,
+            user_agent: None,
+            proxies: Vec::new(),
+            default_headers: HeaderMap::new(),
+            token: None,
+            cookie_store: None,
+            client: None,
+            retry_policy: None,
+            rate_limiter: None,
+            ratelimit_site_requests: false,
+            upload_limits: UploadLimits::new(),
+        }
+    }
+
+    /// Set the base url of the v1 API.
+    ///
+    /// Defaults to `https://api.imgchest.com/`.
+    pub fn api_base_url(&mut self, url: Url) -> &mut Self {
+        self.api_base_url = url;
+        self
+    }
+
+    /// Set the base url of the website.
+    ///
+    /// Defaults to `https://imgchest.com/`.
+    pub fn base_url(&mut self, url: Url) -> &mut Self {
+        self.base_url = url;
+        self
+    }
+
+    /// Set the timeout for an entire request.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the timeout for connecting to a server.
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the user agent.
+    pub fn user_agent(&mut self, user_agent: impl Into<String>) -> &mut Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Add a proxy.
+    pub fn proxy(&mut self, proxy: Proxy) -> &mut Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Add a header to send with every request.
+    pub fn default_header(&mut self, name: HeaderName, value: HeaderValue) -> &mut Self {
+        self.default_headers.insert(name, value);
+        self
+    }
+
+    /// Set the headers to send with every request.
+    ///
+    /// This replaces all previously set default headers.
+    pub fn default_headers(&mut self, headers: HeaderMap) -> &mut Self {
+        self.default_headers = headers;
+        self
+    }
+
+    /// Set the initial token.
+    pub fn token(&mut self, token: impl Into<String>) -> &mut Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Set the initial cookie store.
+    pub fn cookie_store(&mut self, cookie_store: CookieStore) -> &mut Self {
+        self.cookie_store = Some(Arc::new(CookieStoreMutex::new(cookie_store)));
+        self
+    }
+
+    /// Set a cookie store that may be shared with other clients.
+    pub fn shared_cookie_store(&mut self, cookie_store: Arc<CookieStoreMutex>) -> &mut Self {
+        self.cookie_store = Some(cookie_store);
+        self
+    }
+
+    /// Use a preconfigured http client.
+    ///
+    /// The timeouts, user agent, proxies, and default headers of this builder are ignored if this is set.
+    pub fn client(&mut self, client: reqwest::Client) -> &mut Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set the policy for retrying failed requests.
+    pub fn retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Set the ratelimiter.
+    ///
+    /// This may be shared with other clients to share a budget of requests.
+    pub fn rate_limiter(&mut self, rate_limiter: Arc<dyn RateLimiter>) -> &mut Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Set whether to apply the ratelimiter to scraping-based functions and undocumented api calls.
+    pub fn ratelimit_site_requests(&mut self, ratelimit_site_requests: bool) -> &mut Self {
+        self.ratelimit_site_requests = ratelimit_site_requests;
+        self
+    }
+
+    /// Set the limits checked before uploading files.
+    pub fn upload_limits(&mut self, upload_limits: UploadLimits) -> &mut Self {
+        self.upload_limits = upload_limits;
+        self
+    }
+
+    /// Build the client.
+    pub fn build(&self) -> Result<Client, Error> {
+        for url in [&self.api_base_url, &self.base_url] {
+            if url.cannot_be_a_base() {
+                return Err(Error::InvalidBaseUrl {
+                    url: url.as_str().into(),
+                });
+            }
+        }
+
+        let cookie_store = self
+            .cookie_store
+            .clone()
+            .unwrap_or_else(|| Arc::new(CookieStoreMutex::new(CookieStore::new())));
+
+        let client = match self.client.clone() {
+            Some(client) => client,
+            None => {
+                let mut builder = reqwest::Client::builder()
+                    .cookie_provider(cookie_store.clone())
+                    .default_headers(self.default_headers.clone());
+
+                if let Some(timeout) = self.timeout {
+                    builder = builder.timeout(timeout);
+                }
+
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+
+                if let Some(user_agent) = self.user_agent.as_deref() {
+                    builder = builder.user_agent(user_agent);
+                }
+
+                for proxy in self.proxies.iter() {
+                    builder = builder.proxy(proxy.clone());
+                }
+
+                builder.build()?
+            }
+        };
+
+        let state = Arc::new(ClientState::new(self, cookie_store));
+
+        Ok(Client { client, state })
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A builder for creating a post.
 ///
 /// This builder is for the low-level function.
@@ -26,6 +298,9 @@ pub struct CreatePostBuilder {
 
     /// The images of the post
     pub images: Vec<UploadPostFile>,
+
+    /// A channel to report upload progress to.
+    pub progress: Option<watch::Sender<UploadProgress>>,
 }
 
 impl CreatePostBuilder {
@@ -37,6 +312,7 @@ impl CreatePostBuilder {
             anonymous: None,
             nsfw: None,
             images: Vec::new(),
+            progress: None,
         }
     }
 
@@ -73,6 +349,14 @@ impl CreatePostBuilder {
         self.images.push(file);
         self
     }
+
+    /// Set a channel to report upload progress to.
+    ///
+    /// Progress is reset to zero if the upload is retried.
+    pub fn progress(&mut self, sender: watch::Sender<UploadProgress>) -> &mut Self {
+        self.progress = Some(sender);
+        self
+    }
 }
 
 impl Default for CreatePostBuilder {
@@ -89,14 +373,29 @@ pub struct UploadPostFile {
 
     /// The file body
     pub(super) body: reqwest::Body,
+
+    /// The size of the file body, if known
+    pub(super) size: Option<u64>,
+
+    /// The sniffed content type of the file body, if known
+    pub(super) content_type: Option<&'static str>,
+
+    /// The description to set after uploading
+    pub(super) description: Option<String>,
 }
 
 impl UploadPostFile {
     /// Create this from a raw reqwest body.
     pub fn from_body(file_name: &str, body: reqwest::Body) -> Self {
+        let size = body.as_bytes().map(|bytes| usize_to_u64(bytes.len()));
+        let content_type = body.as_bytes().and_then(sniff_content_type);
+
         Self {
             file_name: file_name.into(),
             body,
+            size,
+            content_type,
+            description: None,
         }
     }
 
@@ -113,7 +412,55 @@ impl UploadPostFile {
         Self::from_body(file_name, body)
     }
 
+    /// Try to clone this file.
+    ///
+    /// This only succeeds if the body is not a stream.
+    pub(super) fn try_clone(&self) -> Option<Self> {
+        let body = self.body.as_bytes()?.to_vec();
+
+        let mut file = Self::from_bytes(&self.file_name, body);
+        file.description.clone_from(&self.description);
+
+        Some(file)
+    }
+
+    /// Set the description of this file.
+    ///
+    /// This is set with a follow-up request after the file is uploaded.
+    /// Empty descriptions are ignored.
+    pub fn description(&mut self, description: impl Into<String>) -> &mut Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Get the size of this file in bytes, if known.
+    ///
+    /// This is known for files created from bytes or from a path.
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Get the content type of this file, sniffed from its first bytes.
+    ///
+    /// This is known for files created from bytes or from a path, if the format is recognized.
+    pub fn content_type(&self) -> Option<&'static str> {
+        self.content_type
+    }
+
+    /// Convert this into a multipart form part.
+    pub(super) fn into_part(self) -> Part {
+        let part = match self.size {
+            Some(size) => Part::stream_with_length(self.body, size),
+            None => Part::stream(self.body),
+        };
+        part.file_name(self.file_name)
+    }
+
     /// Create this from a file at the given path.
+    ///
+    /// The file is only read here to get its size and content type.
+    /// It is opened again when it is uploaded, so many of these can exist without running out of file handles.
+    /// Errors opening it then are returned by the upload request.
     pub async fn from_path<P>(path: P) -> std::io::Result<Self>
     where
         P: AsRef<Path>,
@@ -126,9 +473,30 @@ impl UploadPostFile {
             .to_str()
             .ok_or_else(|| std::io::Error::other("file name is not valid unicode"))?;
 
-        let file = tokio::fs::File::open(path).await?;
+        let mut file = tokio::fs::File::open(path).await?;
+        let metadata = file.metadata().await?;
+
+        let mut header = [0; SNIFF_LEN];
+        let mut header_len = 0;
+        while header_len < header.len() {
+            let n = file.read(&mut header[header_len..]).await?;
+            if n == 0 {
+                break;
+            }
+            header_len += n;
+        }
+        drop(file);
 
-        Ok(Self::from_file(file_name, file))
+        let path = path.to_path_buf();
+        let stream = futures_util::stream::once(tokio::fs::File::open(path))
+            .map_ok(|file| FramedRead::new(file, BytesCodec::new()))
+            .try_flatten();
+
+        let mut upload_file = Self::from_body(file_name, reqwest::Body::wrap_stream(stream));
+        upload_file.size = Some(metadata.len());
+        upload_file.content_type = sniff_content_type(&header[..header_len]);
+
+        Ok(upload_file)
     }
 }
 
@@ -195,6 +563,41 @@ pub enum SortOrder {
 
     /// Sort by the oldest posts
     Old,
+
+    /// Sort by the highest scored posts
+    Top,
+}
+
+/// How nsfw posts should be filtered
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum NsfwFilter {
+    /// Include nsfw posts alongside other posts
+    Include,
+
+    /// Only include nsfw posts
+    Only,
+
+    /// Exclude nsfw posts
+    Exclude,
+}
+
+/// The window of time to list posts from
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Timeframe {
+    /// Posts from the last day
+    Day,
+
+    /// Posts from the last week
+    Week,
+
+    /// Posts from the last month
+    Month,
+
+    /// Posts from the last year
+    Year,
+
+    /// Posts from all time
+    All,
 }
 
 /// A builder for listing posts
@@ -215,6 +618,32 @@ pub struct ListPostsBuilder {
 
     /// Whether to list posts from the current user.
     pub profile: bool,
+
+    /// The text to search for.
+    pub search: Option<String>,
+
+    /// How nsfw posts should be filtered.
+    ///
+    /// Listing nsfw posts requires the cookies of a logged in user.
+    pub nsfw: Option<NsfwFilter>,
+
+    /// The window of time to list posts from.
+    ///
+    /// This is only used when sorting by popular or top posts.
+    pub timeframe: Option<Timeframe>,
+
+    /// The username whose favorite posts should be listed.
+    pub favorites: Option<String>,
+
+    /// The max number of posts to get.
+    ///
+    /// This is only used by [`Client::list_posts_stream`].
+    pub max_items: Option<u64>,
+
+    /// The max number of pages to get.
+    ///
+    /// This is only used by [`Client::list_posts_stream`].
+    pub max_pages: Option<u64>,
 }
 
 impl ListPostsBuilder {
@@ -225,6 +654,12 @@ impl ListPostsBuilder {
             page: 1,
             username: None,
             profile: false,
+            search: None,
+            nsfw: None,
+            timeframe: None,
+            favorites: None,
+            max_items: None,
+            max_pages: None,
         }
     }
 
@@ -255,6 +690,50 @@ impl ListPostsBuilder {
         self.profile = profile;
         self
     }
+
+    /// Set the text to search for.
+    pub fn search(&mut self, search: String) -> &mut Self {
+        self.search = Some(search);
+        self
+    }
+
+    /// Set how nsfw posts should be filtered.
+    ///
+    /// Listing nsfw posts requires the cookies of a logged in user.
+    pub fn nsfw(&mut self, nsfw: NsfwFilter) -> &mut Self {
+        self.nsfw = Some(nsfw);
+        self
+    }
+
+    /// Set the window of time to list posts from.
+    ///
+    /// This is only used when sorting by popular or top posts.
+    pub fn timeframe(&mut self, timeframe: Timeframe) -> &mut Self {
+        self.timeframe = Some(timeframe);
+        self
+    }
+
+    /// Set the username whose favorite posts should be listed.
+    pub fn favorites(&mut self, username: String) -> &mut Self {
+        self.favorites = Some(username);
+        self
+    }
+
+    /// Set the max number of posts to get.
+    ///
+    /// This is only used by [`Client::list_posts_stream`].
+    pub fn max_items(&mut self, max_items: u64) -> &mut Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Set the max number of pages to get.
+    ///
+    /// This is only used by [`Client::list_posts_stream`].
+    pub fn max_pages(&mut self, max_pages: u64) -> &mut Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
 }
 
 impl Default for ListPostsBuilder {
@@ -0,0 +1,101 @@
+use super::usize_to_u64;
+use super::UploadPostFile;
+use futures_util::TryStreamExt;
+use http_body_util::BodyDataStream;
+use reqwest::multipart::Part;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio_util::bytes::Bytes;
+
+/// The size of the chunks that in-memory files are split into,
+/// so that their progress can be reported.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The progress of an upload.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// The progress of each file, in upload order.
+    pub files: Vec<FileUploadProgress>,
+
+    /// The number of bytes sent, across all files.
+    pub bytes_sent: u64,
+
+    /// The total number of bytes to send, across all files.
+    ///
+    /// This is `None` if the size of any file is unknown.
+    pub total_bytes: Option<u64>,
+}
+
+/// The progress of a single file in an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileUploadProgress {
+    /// The file name
+    pub file_name: String,
+
+    /// The number of bytes sent
+    pub bytes_sent: u64,
+
+    /// The size of the file, if known
+    pub total_bytes: Option<u64>,
+}
+
+/// Reset the progress to zero for a new upload attempt of the given files.
+pub(super) fn reset_progress(sender: &watch::Sender<UploadProgress>, files: &[UploadPostFile]) {
+    let files: Vec<_> = files
+        .iter()
+        .map(|file| FileUploadProgress {
+            file_name: file.file_name.clone(),
+            bytes_sent: 0,
+            total_bytes: file.size,
+        })
+        .collect();
+    let total_bytes = files.iter().map(|file| file.total_bytes).sum();
+
+    sender.send_replace(UploadProgress {
+        files,
+        bytes_sent: 0,
+        total_bytes,
+    });
+}
+
+/// Convert a file into a multipart form part, reporting bytes as they are read.
+pub(super) fn tracked_part(
+    file: UploadPostFile,
+    index: usize,
+    sender: Arc<watch::Sender<UploadProgress>>,
+) -> Part {
+    let record = move |chunk: &Bytes| {
+        let len = usize_to_u64(chunk.len());
+        sender.send_modify(|progress| {
+            progress.bytes_sent += len;
+            if let Some(file) = progress.files.get_mut(index) {
+                file.bytes_sent += len;
+            }
+        });
+    };
+
+    match file.body.as_bytes() {
+        Some(bytes) => {
+            let bytes = Bytes::copy_from_slice(bytes);
+            let len = bytes.len();
+            let chunks = (0..len).step_by(CHUNK_SIZE).map(move |start| {
+                let end = std::cmp::min(start + CHUNK_SIZE, len);
+                Ok::<_, std::io::Error>(bytes.slice(start..end))
+            });
+            let stream = futures_util::stream::iter(chunks).inspect_ok(record);
+            let body = reqwest::Body::wrap_stream(stream);
+
+            Part::stream_with_length(body, usize_to_u64(len)).file_name(file.file_name)
+        }
+        None => {
+            let stream = BodyDataStream::new(file.body).inspect_ok(record);
+            let body = reqwest::Body::wrap_stream(stream);
+
+            let part = match file.size {
+                Some(size) => Part::stream_with_length(body, size),
+                None => Part::stream(body),
+            };
+            part.file_name(file.file_name)
+        }
+    }
+}
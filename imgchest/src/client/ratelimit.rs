@@ -0,0 +1,213 @@
+use super::parse_retry_after;
+use reqwest::header::HeaderMap;
+use reqwest::header::RETRY_AFTER;
+use std::num::NonZeroU32;
+use std::time::Duration;
+use std::time::Instant;
+use time::OffsetDateTime;
+
+/// The ratelimit status reported by the server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RatelimitStatus {
+    /// The max number of requests allowed in the current window.
+    pub limit: Option<u32>,
+
+    /// The number of requests remaining in the current window.
+    pub remaining: Option<u32>,
+
+    /// The time when the current window resets.
+    pub reset: Option<OffsetDateTime>,
+
+    /// The time this status was observed.
+    pub observed: OffsetDateTime,
+}
+
+impl RatelimitStatus {
+    /// Parse this from response headers.
+    ///
+    /// Returns `None` if the response has no ratelimit headers.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let observed = OffsetDateTime::now_utc();
+        let get_u64 =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+        let add_to_observed = |duration: Duration| -> Option<OffsetDateTime> {
+            observed.checked_add(time::Duration::try_from(duration).ok()?)
+        };
+
+        let limit = get_u64("x-ratelimit-limit").and_then(|value| u32::try_from(value).ok());
+        let remaining =
+            get_u64("x-ratelimit-remaining").and_then(|value| u32::try_from(value).ok());
+        let reset = get_u64("x-ratelimit-reset")
+            .and_then(|value| {
+                // Some servers send a unix timestamp,
+                // while others send the number of seconds until the reset.
+                if value >= 1_000_000_000 {
+                    OffsetDateTime::from_unix_timestamp(i64::try_from(value).ok()?).ok()
+                } else {
+                    add_to_observed(Duration::from_secs(value))
+                }
+            })
+            .or_else(|| {
+                let retry_after = headers.get(RETRY_AFTER)?.to_str().ok()?;
+                add_to_observed(parse_retry_after(retry_after)?)
+            });
+
+        if limit.is_none() && remaining.is_none() && reset.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            limit,
+            remaining,
+            reset,
+            observed,
+        })
+    }
+}
+
+/// A ratelimiter for requests.
+///
+/// This may be shared between clients to share a budget of requests.
+pub trait RateLimiter: std::fmt::Debug + Send + Sync {
+    /// Try to take permission to make a request.
+    ///
+    /// # Returns
+    /// Returns `None` if a request can be made.
+    /// Otherwise, returns the time needed to sleep before calling this again.
+    fn try_acquire(&self) -> Option<Duration>;
+
+    /// Update this ratelimiter with the ratelimit status reported by the server.
+    ///
+    /// By default, this does nothing.
+    fn update(&self, _status: &RatelimitStatus) {}
+}
+
+/// A ratelimiter that uses a token bucket.
+///
+/// Tokens are added to the bucket at a fixed rate, up to a max burst size.
+/// Each request takes a token.
+///
+/// If the server reports its ratelimit status,
+/// the bucket never holds more tokens than the number of requests the server says are remaining.
+/// If the server says no requests are remaining, requests are blocked until the reported reset time.
+#[derive(Debug)]
+pub struct TokenBucketRateLimiter {
+    /// The number of tokens added per second.
+    tokens_per_second: f64,
+
+    /// The max number of tokens.
+    burst: f64,
+
+    state: std::sync::Mutex<TokenBucketState>,
+}
+
+#[derive(Debug)]
+struct TokenBucketState {
+    tokens: f64,
+    last_refilled: Instant,
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucketRateLimiter {
+    /// Make a new ratelimiter that allows `rate` requests per `interval`.
+    ///
+    /// Up to `burst` requests may be made at once.
+    /// The bucket starts full.
+    pub fn new(rate: NonZeroU32, interval: Duration, burst: NonZeroU32) -> Self {
+        let tokens_per_second = f64::from(rate.get()) / interval.as_secs_f64();
+        let burst = f64::from(burst.get());
+
+        Self {
+            tokens_per_second,
+            burst,
+            state: std::sync::Mutex::new(TokenBucketState {
+                tokens: burst,
+                last_refilled: Instant::now(),
+                blocked_until: None,
+            }),
+        }
+    }
+}
+
+impl Default for TokenBucketRateLimiter {
+    /// Make a ratelimiter that allows 40 requests per minute.
+    fn default() -> Self {
+        // Should be 60, but that still triggers the ratelimit.
+        // Add some leeway.
+        let requests_per_minute = NonZeroU32::new(40).unwrap();
+
+        Self::new(
+            requests_per_minute,
+            Duration::from_secs(60),
+            requests_per_minute,
+        )
+    }
+}
+
+impl RateLimiter for TokenBucketRateLimiter {
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+
+        let now = Instant::now();
+
+        // The server told us to wait until its window resets.
+        if let Some(blocked_until) = state.blocked_until {
+            if now < blocked_until {
+                return Some(blocked_until - now);
+            }
+
+            state.blocked_until = None;
+            state.tokens = self.burst;
+            state.last_refilled = now;
+        }
+
+        let elapsed = now.saturating_duration_since(state.last_refilled);
+        state.tokens =
+            (state.tokens + (elapsed.as_secs_f64() * self.tokens_per_second)).min(self.burst);
+        state.last_refilled = now;
+
+        // If we are allowed to make a request now, make it.
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            return None;
+        }
+
+        // Otherwise, sleep until the next token is added.
+        let duration = (1.0 - state.tokens) / self.tokens_per_second;
+
+        Some(Duration::try_from_secs_f64(duration).unwrap_or(Duration::ZERO))
+    }
+
+    fn update(&self, status: &RatelimitStatus) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+
+        let remaining = match status.remaining {
+            Some(remaining) => remaining,
+            None => return,
+        };
+        state.tokens = state.tokens.min(f64::from(remaining));
+
+        if remaining == 0 {
+            let wait = status
+                .reset
+                .map(|reset| reset - OffsetDateTime::now_utc())
+                .and_then(|wait| Duration::try_from(wait).ok());
+
+            if let Some(wait) = wait {
+                state.blocked_until = Some(Instant::now() + wait);
+            }
+        }
+    }
+}
+
+/// A ratelimiter that never limits requests.
+///
+/// This is useful for tests.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NoopRateLimiter;
+
+impl RateLimiter for NoopRateLimiter {
+    fn try_acquire(&self) -> Option<Duration> {
+        None
+    }
+}
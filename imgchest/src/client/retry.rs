@@ -0,0 +1,180 @@
+use crate::Error;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// A policy for retrying failed requests.
+///
+/// Only requests with bodies that can be cloned may be retried.
+/// Bodies created from streams, like files, cannot be cloned.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first.
+    ///
+    /// Defaults to 3.
+    pub max_attempts: u32,
+
+    /// The delay before the first retry.
+    ///
+    /// This doubles for each following retry.
+    /// Defaults to 500 milliseconds.
+    pub base_delay: Duration,
+
+    /// The maximum delay between attempts.
+    ///
+    /// If the server asks to wait longer than this with a Retry-After header,
+    /// the request is not retried.
+    /// Defaults to 30 seconds.
+    pub max_delay: Duration,
+
+    /// Whether to randomize delays.
+    ///
+    /// This picks a delay between half and all of the computed delay.
+    /// Defaults to true.
+    pub jitter: bool,
+
+    /// The response statuses that should be retried.
+    ///
+    /// Defaults to 429, 500, 502, 503, and 504.
+    pub retry_statuses: Vec<StatusCode>,
+
+    /// Whether to retry if connecting or sending the request failed.
+    ///
+    /// Defaults to true.
+    pub retry_connect_errors: bool,
+
+    /// Whether to retry if the request timed out.
+    ///
+    /// Defaults to true.
+    pub retry_timeouts: bool,
+
+    /// Whether to retry requests that are not idempotent.
+    ///
+    /// These are requests that create posts, add images to posts, and toggle favorites.
+    /// Retrying these may result in duplicate posts or images if the server processed the failed attempt.
+    /// Uploads are only retried if every file was created from bytes.
+    /// Defaults to false.
+    pub retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Create a new policy with the default settings.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_connect_errors: true,
+            retry_timeouts: true,
+            retry_non_idempotent: false,
+        }
+    }
+
+    /// Set the maximum number of attempts, including the first.
+    pub fn max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn base_delay(&mut self, base_delay: Duration) -> &mut Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Set the maximum delay between attempts.
+    pub fn max_delay(&mut self, max_delay: Duration) -> &mut Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set whether to randomize delays.
+    pub fn jitter(&mut self, jitter: bool) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the response statuses that should be retried.
+    pub fn retry_statuses(&mut self, retry_statuses: Vec<StatusCode>) -> &mut Self {
+        self.retry_statuses = retry_statuses;
+        self
+    }
+
+    /// Set whether to retry if connecting or sending the request failed.
+    pub fn retry_connect_errors(&mut self, retry_connect_errors: bool) -> &mut Self {
+        self.retry_connect_errors = retry_connect_errors;
+        self
+    }
+
+    /// Set whether to retry if the request timed out.
+    pub fn retry_timeouts(&mut self, retry_timeouts: bool) -> &mut Self {
+        self.retry_timeouts = retry_timeouts;
+        self
+    }
+
+    /// Set whether to retry requests that are not idempotent.
+    pub fn retry_non_idempotent(&mut self, retry_non_idempotent: bool) -> &mut Self {
+        self.retry_non_idempotent = retry_non_idempotent;
+        self
+    }
+
+    /// Check whether a request that failed with the given error should be retried.
+    pub fn is_retryable(&self, error: &Error) -> bool {
+        match error {
+            Error::Reqwest(error) if error.is_timeout() => self.retry_timeouts,
+            Error::Reqwest(error) if error.is_connect() || error.is_request() => {
+                self.retry_connect_errors
+            }
+            error => error
+                .status()
+                .is_some_and(|status| self.retry_statuses.contains(&status)),
+        }
+    }
+
+    /// Get the delay before the next attempt.
+    ///
+    /// `attempt` is the number of the attempt that failed, starting at 1.
+    ///
+    /// # Returns
+    /// Returns `None` if the server asked to wait longer than the max delay.
+    pub(super) fn get_delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if let Error::RateLimited {
+            retry_after: Some(retry_after),
+        } = error
+        {
+            if *retry_after > self.max_delay {
+                return None;
+            }
+
+            return Some(*retry_after);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        if !self.jitter {
+            return Some(delay);
+        }
+
+        let half_delay = delay / 2;
+        let jitter = half_delay.mul_f64(fastrand::f64());
+
+        Some(half_delay + jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
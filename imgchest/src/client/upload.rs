@@ -0,0 +1,119 @@
+use super::UploadPostFile;
+use crate::Error;
+
+/// The number of bytes needed to sniff the content type of a file.
+pub(super) const SNIFF_LEN: usize = 16;
+
+/// Limits checked before uploading files.
+///
+/// Checking these locally avoids sending a large upload that the server would reject.
+#[derive(Debug, Clone, Default)]
+pub struct UploadLimits {
+    /// The maximum size of a single file, in bytes.
+    ///
+    /// Files with an unknown size are not checked.
+    /// Defaults to no limit.
+    pub max_file_size: Option<u64>,
+
+    /// The maximum number of images in a single request.
+    ///
+    /// Defaults to no limit.
+    pub max_images: Option<usize>,
+
+    /// The allowed content types, like "image/png".
+    ///
+    /// If specified, files whose content type cannot be sniffed are rejected.
+    /// Defaults to allowing all content types.
+    pub allowed_content_types: Option<Vec<String>>,
+}
+
+impl UploadLimits {
+    /// Create new limits that allow everything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum size of a single file, in bytes.
+    pub fn max_file_size(&mut self, max_file_size: u64) -> &mut Self {
+        self.max_file_size = Some(max_file_size);
+        self
+    }
+
+    /// Set the maximum number of images in a single request.
+    pub fn max_images(&mut self, max_images: usize) -> &mut Self {
+        self.max_images = Some(max_images);
+        self
+    }
+
+    /// Set the allowed content types.
+    pub fn allowed_content_types<I, S>(&mut self, content_types: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_content_types = Some(content_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Check the given files against these limits.
+    pub(super) fn validate(&self, images: &[UploadPostFile]) -> Result<(), Error> {
+        if let Some(max_images) = self.max_images {
+            if images.len() > max_images {
+                return Err(Error::TooManyImages {
+                    count: images.len(),
+                    max: max_images,
+                });
+            }
+        }
+
+        for file in images {
+            if let (Some(size), Some(max_size)) = (file.size(), self.max_file_size) {
+                if size > max_size {
+                    return Err(Error::UploadTooLarge {
+                        file_name: file.file_name.as_str().into(),
+                        size,
+                        max_size,
+                    });
+                }
+            }
+
+            if let Some(allowed_content_types) = self.allowed_content_types.as_ref() {
+                let content_type = file.content_type();
+                let is_allowed = content_type.is_some_and(|content_type| {
+                    allowed_content_types
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(content_type))
+                });
+                if !is_allowed {
+                    return Err(Error::UnsupportedUploadFormat {
+                        file_name: file.file_name.as_str().into(),
+                        content_type: content_type.map(Into::into),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Guess the content type of a file from its first bytes.
+pub(super) fn sniff_content_type(bytes: &[u8]) -> Option<&'static str> {
+    let content_type = match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => "image/png",
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [b'B', b'M', ..] => "image/bmp",
+        [0x1A, 0x45, 0xDF, 0xA3, ..] => "video/webm",
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] => match brand {
+            [b'a', b'v', b'i', b'f' | b's', ..] => "image/avif",
+            [b'h', b'e', b'i', b'c' | b'x', ..] => "image/heic",
+            [b'm', b'i', b'f', b'1', ..] => "image/heif",
+            [b'q', b't', b' ', b' ', ..] => "video/quicktime",
+            _ => "video/mp4",
+        },
+        _ => return None,
+    };
+    Some(content_type)
+}
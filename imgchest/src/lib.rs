@@ -3,30 +3,51 @@ mod model;
 mod serde;
 
 pub use self::client::Client;
+pub use self::client::ClientBuilder;
 pub use self::client::CreatePostBuilder;
+pub use self::client::FileUploadProgress;
 pub use self::client::ListPostsBuilder;
+pub use self::client::NoopRateLimiter;
+pub use self::client::NsfwFilter;
+pub use self::client::RateLimiter;
+pub use self::client::RatelimitStatus;
+pub use self::client::RetryPolicy;
 pub use self::client::SortOrder;
+pub use self::client::Timeframe;
+pub use self::client::TokenBucketRateLimiter;
 pub use self::client::UpdatePostBuilder;
+pub use self::client::UploadLimits;
 pub use self::client::UploadPostFile;
+pub use self::client::UploadProgress;
 use self::model::ApiCompletedResponse;
+use self::model::ApiErrorResponse;
 use self::model::ApiResponse;
 use self::model::ApiUpdateFilesBulkRequest;
 pub use self::model::FileUpdate;
 pub use self::model::InvalidScrapedPostError;
 pub use self::model::InvalidScrapedUserError;
 pub use self::model::ListPostsPost;
+pub use self::model::MediaKind;
 pub use self::model::Post;
 pub use self::model::PostFile;
 pub use self::model::PostPrivacy;
+pub use self::model::ScrapedComment;
 pub use self::model::ScrapedPost;
 pub use self::model::ScrapedPostFile;
 pub use self::model::ScrapedUser;
 pub use self::model::User;
+pub use reqwest::header::HeaderMap;
+pub use reqwest::header::HeaderName;
+pub use reqwest::header::HeaderValue;
 pub use reqwest::Body;
+pub use reqwest::Proxy;
+pub use reqwest::StatusCode;
 pub use reqwest::Url;
 pub use reqwest_cookie_store::CookieStore;
 pub use reqwest_cookie_store::CookieStoreMutex;
 pub use reqwest_cookie_store::RawCookie;
+use std::collections::HashMap;
+use std::time::Duration;
 
 /// The error
 #[derive(Debug, thiserror::Error)]
@@ -77,14 +98,159 @@ pub enum Error {
     /// The title is too short.
     #[error("title too short, must be at least 3 characters")]
     TitleTooShort,
+
+    /// Too many images were given for a single request
+    #[error("too many images, got {count} but the max is {max}")]
+    TooManyImages {
+        /// The number of images given
+        count: usize,
+
+        /// The max number of images
+        max: usize,
+    },
+
+    /// A file is larger than the max upload size
+    #[error("file \"{file_name}\" is too large, {size} bytes is over the max of {max_size} bytes")]
+    UploadTooLarge {
+        /// The name of the file
+        file_name: Box<str>,
+
+        /// The size of the file in bytes
+        size: u64,
+
+        /// The max file size in bytes
+        max_size: u64,
+    },
+
+    /// A file is not in an allowed format
+    #[error("file \"{file_name}\" has an unsupported format")]
+    UnsupportedUploadFormat {
+        /// The name of the file
+        file_name: Box<str>,
+
+        /// The sniffed content type, if it could be determined
+        content_type: Option<Box<str>>,
+    },
+
+    /// The files were uploaded, but setting their descriptions failed
+    #[error("failed to set file descriptions")]
+    FileDescriptions {
+        /// The post the files were uploaded to
+        post: Box<Post>,
+
+        /// The error that caused setting the descriptions to fail
+        #[source]
+        error: Box<Error>,
+    },
+
+    /// A batch of a batched upload failed
+    #[error(
+        "batch {batch} of upload to post {} failed after {uploaded} images were uploaded",
+        .post_id.as_deref().unwrap_or("<unknown>")
+    )]
+    BatchUpload {
+        /// The id of the post, if it was created
+        post_id: Option<Box<str>>,
+
+        /// The index of the batch that failed, starting at 0
+        batch: usize,
+
+        /// The number of images that were uploaded before the failure
+        uploaded: usize,
+
+        /// The error that caused the batch to fail
+        #[source]
+        error: Box<Error>,
+    },
+
+    /// The request was not authorized, likely due to a missing or invalid token
+    #[error("unauthorized")]
+    Unauthorized {
+        /// The error message from the server
+        message: Option<Box<str>>,
+    },
+
+    /// The current user is not allowed to do this
+    #[error("forbidden")]
+    Forbidden {
+        /// The error message from the server
+        message: Option<Box<str>>,
+    },
+
+    /// The requested object does not exist
+    #[error("not found")]
+    NotFound {
+        /// The error message from the server
+        message: Option<Box<str>>,
+    },
+
+    /// The server hit its ratelimit
+    #[error("ratelimited")]
+    RateLimited {
+        /// The time to wait before retrying, if the server specified it
+        retry_after: Option<Duration>,
+    },
+
+    /// The server rejected the request data
+    #[error("validation failed")]
+    Validation {
+        /// The error message from the server
+        message: Option<Box<str>>,
+
+        /// Error messages, keyed by the name of the invalid field
+        field_errors: HashMap<Box<str>, Vec<Box<str>>>,
+    },
+
+    /// The server returned some other error status
+    #[error("server returned status {status}")]
+    Server {
+        /// The status code
+        status: StatusCode,
+
+        /// The error message from the server
+        message: Option<Box<str>>,
+    },
+
+    /// A base url cannot be used as a base
+    #[error("base url \"{url}\" cannot be a base")]
+    InvalidBaseUrl {
+        /// The invalid url
+        url: Box<str>,
+    },
+}
+
+impl Error {
+    /// Get the response status that caused this error, if there was one.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Reqwest(error) => error.status(),
+            Self::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+            Self::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            Self::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Self::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Self::Validation { .. } => Some(StatusCode::UNPROCESSABLE_ENTITY),
+            Self::Server { status, .. } => Some(*status),
+            Self::FileDescriptions { error, .. } => error.status(),
+            Self::BatchUpload { error, .. } => error.status(),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::sync::Arc;
     use std::sync::OnceLock;
     use time::format_description::well_known::Iso8601;
     use time::OffsetDateTime;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::matchers::query_param;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
 
     const POST_ID: &str = "3qe4gdvj4j2";
     const GIF_POST_ID: &str = "pwl7lgepyx2";
@@ -119,6 +285,50 @@ mod test {
         })
     }
 
+    /// Make a client that sends all requests to the given mock server.
+    fn mock_client(server: &MockServer) -> Client {
+        let url = Url::parse(&server.uri()).expect("invalid mock server url");
+        Client::builder()
+            .api_base_url(url.clone())
+            .base_url(url)
+            .build()
+            .expect("failed to build client")
+    }
+
+    /// Escape a string so it can be used as an html attribute value.
+    fn escape_html_attr(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('"', "&quot;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    fn mock_post_json() -> serde_json::Value {
+        serde_json::json!({
+            "id": "3qe4gdvj4j2",
+            "title": "Donkey Kong - Video Game From The Mid 80's",
+            "username": "LunarLandr",
+            "privacy": "public",
+            "report_status": 1,
+            "views": 198,
+            "nsfw": 0,
+            "image_count": 1,
+            "created": "2019-11-03T00:36:00.000000Z",
+            "images": [
+                {
+                    "id": "nw7w6cmlvye",
+                    "description": "test description",
+                    "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                    "position": 1,
+                    "created": "2019-11-03T00:36:00.000000Z",
+                    "original_name": null
+                }
+            ],
+            "delete_url": null
+        })
+    }
+
     #[tokio::test]
     async fn get_scraped_post() {
         let client = Client::new();
@@ -176,6 +386,7 @@ mod test {
         assert!(&*post.images[0].id == "6yxkcz5ml7w");
         assert!(post.images[0].description.as_deref() == Some("Notice how inserting an AGIF is now supported, but does not want to be moved from its initial position."));
         assert!(&*post.images[0].link == "https://cdn.imgchest.com/files/6yxkcz5ml7w.gif");
+        assert!(post.images[0].media_kind() == MediaKind::AnimatedGif);
 
         dbg!(&post);
     }
@@ -197,6 +408,7 @@ mod test {
         assert!(&*post.images[0].id == "e4gdcbqe294");
         assert!(post.images[0].description.is_none());
         assert!(&*post.images[0].link == "https://cdn.imgchest.com/files/e4gdcbqe294.mp4");
+        assert!(post.images[0].media_kind() == MediaKind::Video);
 
         dbg!(&post);
     }
@@ -468,4 +680,1675 @@ mod test {
 
         assert!(matches!(err, Error::MissingDescription));
     }
+
+    #[test]
+    fn client_builder_invalid_base_url() {
+        let err = Client::builder()
+            .base_url(Url::parse("data:text/plain,imgchest").unwrap())
+            .build()
+            .expect_err("base url should have been invalid");
+
+        assert!(matches!(err, Error::InvalidBaseUrl { .. }));
+    }
+
+    #[tokio::test]
+    async fn mock_get_post() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let post = client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+
+        assert!(&*post.id == "3qe4gdvj4j2");
+        assert!(&*post.username == "LunarLandr");
+        assert!(post.privacy == PostPrivacy::Public);
+        assert!(post.image_count == 1);
+        assert!(&*post.images[0].id == "nw7w6cmlvye");
+        assert!(post.images[0].description.as_deref() == Some("test description"));
+    }
+
+    #[tokio::test]
+    async fn mock_get_scraped_post() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [
+                        {
+                            "id": "nw7w6cmlvye",
+                            "description": "test <description> & \"quotes\"",
+                            "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                            "position": 1,
+                            "created": "2023-04-01T12:00:00.000000Z",
+                            "width": 640,
+                            "height": "480"
+                        }
+                    ],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": {
+                        "username": "LunarLandr"
+                    },
+                    "views": 198,
+                    "privacy": "hidden",
+                    "report_status": 1,
+                    "created": "2023-04-01T12:00:00.000000Z",
+                    "votes": "5",
+                    "favorites_count": 3,
+                    "description": "a post description",
+                    "tags": ["retro", { "id": 1, "name": "arcade" }]
+                }
+            }
+        });
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"app\" data-page=\"{}\"></div></body></html>",
+            escape_html_attr(&page_data.to_string())
+        );
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/p/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(html))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let post = client
+            .get_scraped_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get scraped post");
+
+        assert!(&*post.id == "3qe4gdvj4j2");
+        assert!(&*post.username == "LunarLandr");
+        assert!(post.privacy == Some(PostPrivacy::Hidden));
+        assert!(post.report_status == Some(1));
+        assert!(post.created.is_some());
+        assert!(post.score == Some(5));
+        assert!(post.favorites == Some(3));
+        assert!(post.description.as_deref() == Some("a post description"));
+        assert!(post.tags.len() == 2);
+        assert!(&*post.tags[0] == "retro");
+        assert!(&*post.tags[1] == "arcade");
+        assert!(post.image_count == 1);
+        assert!(post.images[0].description.as_deref() == Some("test <description> & \"quotes\""));
+        assert!(post.images[0].created.is_some());
+        assert!(post.images[0].width == Some(640));
+        assert!(post.images[0].height == Some(480));
+    }
+
+    #[tokio::test]
+    async fn mock_list_posts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .and(query_param("sort", "new"))
+            .and(query_param("page", "2"))
+            .and(query_param("username", "LunarLandr"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    {
+                        "id": "3qe4gdvj4j2",
+                        "title": "Donkey Kong - Video Game From The Mid 80's",
+                        "slug": "donkey-kong-video-game-from-the-mid-80s",
+                        "link": "https://imgchest.com/p/3qe4gdvj4j2",
+                        "nsfw": 0,
+                        "score": "5",
+                        "comments": 1,
+                        "views": 198,
+                        "thumbnail": {
+                            "id": "nw7w6cmlvye",
+                            "description": null,
+                            "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png"
+                        }
+                    }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let mut builder = ListPostsBuilder::new();
+        builder
+            .sort(SortOrder::New)
+            .page(2)
+            .username("LunarLandr".to_string());
+        let posts = client
+            .list_posts(builder)
+            .await
+            .expect("failed to list posts");
+
+        assert!(posts.len() == 1);
+        assert!(&*posts[0].id == "3qe4gdvj4j2");
+        assert!(posts[0].score == 5);
+    }
+
+    #[tokio::test]
+    async fn mock_client_builder_options() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .and(header("Authorization", "Bearer token"))
+            .and(header("User-Agent", "imgchest-test"))
+            .and(header("X-Test", "test"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&server.uri()).expect("invalid mock server url");
+        let client = Client::builder()
+            .api_base_url(url)
+            .timeout(std::time::Duration::from_secs(10))
+            .connect_timeout(std::time::Duration::from_secs(5))
+            .user_agent("imgchest-test")
+            .default_header(
+                HeaderName::from_static("x-test"),
+                HeaderValue::from_static("test"),
+            )
+            .token("token")
+            .build()
+            .expect("failed to build client");
+
+        client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+    }
+
+    #[tokio::test]
+    async fn mock_client_builder_custom_client() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .and(header("Authorization", "Bearer token"))
+            .and(header("User-Agent", "custom-client"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cookie_store = Arc::new(CookieStoreMutex::new(CookieStore::default()));
+        let http_client = reqwest::Client::builder()
+            .user_agent("custom-client")
+            .cookie_provider(cookie_store.clone())
+            .build()
+            .expect("failed to build http client");
+
+        let url = Url::parse(&server.uri()).expect("invalid mock server url");
+        let client = Client::builder()
+            .api_base_url(url)
+            .user_agent("ignored")
+            .client(http_client)
+            .shared_cookie_store(cookie_store.clone())
+            .token("token")
+            .build()
+            .expect("failed to build client");
+
+        assert!(Arc::ptr_eq(client.get_cookie_store(), &cookie_store));
+
+        client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+    }
+
+    #[tokio::test]
+    async fn mock_error_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/p/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("<html></html>"))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let err = client
+            .get_scraped_post("3qe4gdvj4j2")
+            .await
+            .expect_err("post should not exist");
+
+        assert!(matches!(err, Error::NotFound { message: None }));
+    }
+
+    #[tokio::test]
+    async fn mock_error_unauthorized() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(
+                ResponseTemplate::new(401)
+                    .set_body_json(serde_json::json!({ "message": "Unauthenticated." })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let err = client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect_err("token should be invalid");
+
+        match err {
+            Error::Unauthorized { message } => {
+                assert!(message.as_deref() == Some("Unauthenticated."));
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_error_validation() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "message": "The privacy field is invalid.",
+                "errors": {
+                    "privacy": ["The privacy field is invalid."]
+                }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let mut builder = UpdatePostBuilder::new();
+        builder.privacy(PostPrivacy::Secret);
+        let err = client
+            .update_post("3qe4gdvj4j2", builder)
+            .await
+            .expect_err("update should have failed validation");
+
+        match err {
+            Error::Validation {
+                message,
+                field_errors,
+            } => {
+                assert!(message.as_deref() == Some("The privacy field is invalid."));
+                assert!(field_errors.len() == 1);
+                assert!(&*field_errors["privacy"][0] == "The privacy field is invalid.");
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_error_ratelimited() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("Retry-After", "30")
+                    .set_body_json(serde_json::json!({ "message": "Too Many Attempts." })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let err = client
+            .list_posts(ListPostsBuilder::new())
+            .await
+            .expect_err("request should have been ratelimited");
+
+        match err {
+            Error::RateLimited { retry_after } => {
+                assert!(retry_after == Some(std::time::Duration::from_secs(30)));
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_error_server() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/v1/file/nw7w6cmlvye"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let err = client
+            .delete_file("nw7w6cmlvye")
+            .await
+            .expect_err("server should have been unavailable");
+
+        assert!(matches!(
+            err,
+            Error::Server {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: None,
+            }
+        ));
+    }
+
+    /// Make a client that sends all requests to the given mock server and retries quickly.
+    fn mock_retry_client(server: &MockServer, retry_policy: RetryPolicy) -> Client {
+        let url = Url::parse(&server.uri()).expect("invalid mock server url");
+        Client::builder()
+            .api_base_url(url.clone())
+            .base_url(url)
+            .retry_policy(retry_policy)
+            .token("token")
+            .build()
+            .expect("failed to build client")
+    }
+
+    fn fast_retry_policy() -> RetryPolicy {
+        let mut retry_policy = RetryPolicy::new();
+        retry_policy
+            .base_delay(std::time::Duration::from_millis(1))
+            .jitter(false);
+        retry_policy
+    }
+
+    #[tokio::test]
+    async fn mock_retry_success() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_retry_client(&server, fast_retry_policy());
+        let post = client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+
+        assert!(&*post.id == "3qe4gdvj4j2");
+    }
+
+    #[tokio::test]
+    async fn mock_retry_exhausted() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .respond_with(ResponseTemplate::new(502))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let mut retry_policy = fast_retry_policy();
+        retry_policy.max_attempts(2);
+        let client = mock_retry_client(&server, retry_policy);
+        let err = client
+            .list_posts(ListPostsBuilder::new())
+            .await
+            .expect_err("all attempts should have failed");
+
+        assert!(err.status() == Some(StatusCode::BAD_GATEWAY));
+    }
+
+    #[tokio::test]
+    async fn mock_retry_not_retryable() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_retry_client(&server, fast_retry_policy());
+        let err = client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect_err("post should not exist");
+
+        assert!(matches!(err, Error::NotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn mock_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/p/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/p/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_retry_client(&server, fast_retry_policy());
+        let err = client
+            .get_scraped_post("3qe4gdvj4j2")
+            .await
+            .expect_err("request should have been ratelimited");
+
+        // The second Retry-After is longer than the max delay, so it should not be retried.
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(_)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn mock_retry_non_idempotent() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_retry_client(&server, fast_retry_policy());
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_bytes("test.png", vec![0; 16]));
+        let err = client
+            .create_post(builder)
+            .await
+            .expect_err("server should have been unavailable");
+        assert!(err.status() == Some(StatusCode::SERVICE_UNAVAILABLE));
+        server.verify().await;
+        server.reset().await;
+
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut retry_policy = fast_retry_policy();
+        retry_policy.retry_non_idempotent(true);
+        let client = mock_retry_client(&server, retry_policy);
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_bytes("test.png", vec![0; 16]));
+        client
+            .create_post(builder)
+            .await
+            .expect("failed to create post");
+    }
+
+    #[test]
+    fn token_bucket_rate_limiter() {
+        let rate_limiter = TokenBucketRateLimiter::new(
+            std::num::NonZeroU32::new(1).unwrap(),
+            std::time::Duration::from_secs(60),
+            std::num::NonZeroU32::new(2).unwrap(),
+        );
+
+        assert!(rate_limiter.try_acquire().is_none());
+        assert!(rate_limiter.try_acquire().is_none());
+
+        let sleep_duration = rate_limiter
+            .try_acquire()
+            .expect("bucket should have been empty");
+        assert!(sleep_duration > std::time::Duration::from_secs(59));
+        assert!(sleep_duration <= std::time::Duration::from_secs(60));
+    }
+
+    /// A ratelimiter that counts how many times it was used.
+    #[derive(Debug, Default)]
+    struct CountingRateLimiter {
+        count: std::sync::atomic::AtomicU32,
+    }
+
+    impl RateLimiter for CountingRateLimiter {
+        fn try_acquire(&self) -> Option<std::time::Duration> {
+            self.count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_ratelimit_site_requests() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": []
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&server.uri()).expect("invalid mock server url");
+        let rate_limiter = Arc::new(CountingRateLimiter::default());
+        let mut builder = Client::builder();
+        builder
+            .api_base_url(url.clone())
+            .base_url(url)
+            .token("token")
+            .rate_limiter(rate_limiter.clone());
+
+        let client = builder.build().expect("failed to build client");
+        client
+            .list_posts(ListPostsBuilder::new())
+            .await
+            .expect("failed to list posts");
+        client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+        assert!(rate_limiter.count.load(std::sync::atomic::Ordering::SeqCst) == 1);
+
+        let client = builder
+            .ratelimit_site_requests(true)
+            .build()
+            .expect("failed to build client");
+        client
+            .list_posts(ListPostsBuilder::new())
+            .await
+            .expect("failed to list posts");
+        assert!(rate_limiter.count.load(std::sync::atomic::Ordering::SeqCst) == 2);
+    }
+
+    #[test]
+    fn token_bucket_rate_limiter_update() {
+        let rate_limiter = TokenBucketRateLimiter::default();
+
+        let mut status = RatelimitStatus {
+            limit: Some(60),
+            remaining: Some(1),
+            reset: None,
+            observed: OffsetDateTime::now_utc(),
+        };
+        rate_limiter.update(&status);
+        assert!(rate_limiter.try_acquire().is_none());
+        assert!(rate_limiter.try_acquire().is_some());
+
+        status.remaining = Some(0);
+        status.reset = Some(OffsetDateTime::now_utc() + time::Duration::minutes(10));
+        rate_limiter.update(&status);
+        let sleep_duration = rate_limiter
+            .try_acquire()
+            .expect("requests should have been blocked until the reset");
+        assert!(sleep_duration > std::time::Duration::from_secs(9 * 60));
+    }
+
+    #[tokio::test]
+    async fn mock_ratelimit_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/post/3qe4gdvj4j2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("X-RateLimit-Limit", "60")
+                    .insert_header("X-RateLimit-Remaining", "59")
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/user/LunarLandr"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("X-RateLimit-Limit", "60")
+                    .insert_header("X-RateLimit-Remaining", "0")
+                    .insert_header("Retry-After", "30"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+        assert!(client.ratelimit_status().is_none());
+
+        client
+            .get_post("3qe4gdvj4j2")
+            .await
+            .expect("failed to get post");
+        let status = client.ratelimit_status().expect("missing ratelimit status");
+        assert!(status.limit == Some(60));
+        assert!(status.remaining == Some(59));
+        assert!(status.reset.is_none());
+
+        client
+            .get_user("LunarLandr")
+            .await
+            .expect_err("request should have been ratelimited");
+        let status = client.ratelimit_status().expect("missing ratelimit status");
+        assert!(status.remaining == Some(0));
+        let reset = status.reset.expect("missing reset");
+        assert!(reset - status.observed == time::Duration::seconds(30));
+    }
+
+    fn mock_list_posts_post(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "title": "test",
+            "slug": "test",
+            "link": format!("https://imgchest.com/p/{id}"),
+            "nsfw": 0,
+            "score": 0,
+            "comments": 0,
+            "views": 0,
+            "thumbnail": {
+                "id": "nw7w6cmlvye",
+                "description": null,
+                "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png"
+            }
+        })
+    }
+
+    async fn mock_list_posts_pages(server: &MockServer) {
+        let pages = [
+            vec![
+                mock_list_posts_post("aaaaaaaaaaa"),
+                mock_list_posts_post("bbbbbbbbbbb"),
+            ],
+            vec![
+                mock_list_posts_post("bbbbbbbbbbb"),
+                mock_list_posts_post("ccccccccccc"),
+            ],
+            vec![mock_list_posts_post("ddddddddddd")],
+            vec![],
+        ];
+        for (i, page) in pages.into_iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path("/api/posts"))
+                .and(query_param("page", (i + 1).to_string()))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": page })),
+                )
+                .mount(server)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_list_posts_stream() {
+        use futures_util::TryStreamExt;
+
+        let server = MockServer::start().await;
+        mock_list_posts_pages(&server).await;
+
+        let client = mock_client(&server);
+        let posts: Vec<_> = tokio::spawn(
+            client
+                .list_posts_stream(ListPostsBuilder::new())
+                .try_collect(),
+        )
+        .await
+        .expect("failed to join task")
+        .expect("failed to list posts");
+        let ids: Vec<_> = posts.iter().map(|post| &*post.id).collect();
+        assert!(ids == ["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc", "ddddddddddd"]);
+
+        let mut builder = ListPostsBuilder::new();
+        builder.max_pages(2);
+        let posts: Vec<_> = client
+            .list_posts_stream(builder)
+            .try_collect()
+            .await
+            .expect("failed to list posts");
+        let ids: Vec<_> = posts.iter().map(|post| &*post.id).collect();
+        assert!(ids == ["aaaaaaaaaaa", "bbbbbbbbbbb", "ccccccccccc"]);
+
+        let mut builder = ListPostsBuilder::new();
+        builder.page(2).max_items(1);
+        let posts: Vec<_> = client
+            .list_posts_stream(builder)
+            .try_collect()
+            .await
+            .expect("failed to list posts");
+        let ids: Vec<_> = posts.iter().map(|post| &*post.id).collect();
+        assert!(ids == ["bbbbbbbbbbb"]);
+    }
+
+    #[tokio::test]
+    async fn mock_list_posts_stream_error() {
+        use futures_util::StreamExt;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let results: Vec<_> = client
+            .list_posts_stream(ListPostsBuilder::new())
+            .collect()
+            .await;
+        assert!(results.len() == 1);
+        assert!(results[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn mock_list_posts_stream_repeated_page() {
+        use futures_util::TryStreamExt;
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    mock_list_posts_post("aaaaaaaaaaa"),
+                    mock_list_posts_post("bbbbbbbbbbb"),
+                ]
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let posts: Vec<_> = client
+            .list_posts_stream(ListPostsBuilder::new())
+            .try_collect()
+            .await
+            .expect("failed to list posts");
+        let ids: Vec<_> = posts.iter().map(|post| &*post.id).collect();
+        assert!(ids == ["aaaaaaaaaaa", "bbbbbbbbbbb"]);
+    }
+
+    #[tokio::test]
+    async fn mock_list_posts_filters() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/posts"))
+            .and(query_param("sort", "top"))
+            .and(query_param("search", "donkey kong"))
+            .and(query_param("nsfw", "only"))
+            .and(query_param("time", "week"))
+            .and(query_param("favorites", "LunarLandr"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [mock_list_posts_post("3qe4gdvj4j2")]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let mut builder = ListPostsBuilder::new();
+        builder
+            .sort(SortOrder::Top)
+            .search("donkey kong".to_string())
+            .nsfw(NsfwFilter::Only)
+            .timeframe(Timeframe::Week)
+            .favorites("LunarLandr".to_string());
+        let posts = client
+            .list_posts(builder)
+            .await
+            .expect("failed to list posts");
+
+        assert!(posts.len() == 1);
+    }
+
+    #[tokio::test]
+    async fn mock_get_scraped_comments() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": {
+                        "username": "LunarLandr"
+                    },
+                    "views": 198,
+                    "comments": [
+                        {
+                            "id": 1,
+                            "user": { "username": "LunarLandr" },
+                            "comment": "first",
+                            "score": "2",
+                            "created_at": "2023-04-01T12:00:00.000000Z",
+                            "parent_id": null,
+                            "replies": [
+                                {
+                                    "id": 2,
+                                    "user": { "username": "other" },
+                                    "comment": "nested reply",
+                                    "score": 0
+                                }
+                            ]
+                        },
+                        {
+                            "id": 3,
+                            "user": null,
+                            "comment": "flat reply",
+                            "score": -1,
+                            "parent_id": 2
+                        },
+                        {
+                            "id": 4,
+                            "user": { "username": "other" },
+                            "comment": "orphan",
+                            "parent_id": 100
+                        }
+                    ]
+                }
+            }
+        });
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"app\" data-page=\"{}\"></div></body></html>",
+            escape_html_attr(&page_data.to_string())
+        );
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/p/3qe4gdvj4j2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(html))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let comments = client
+            .get_scraped_comments("3qe4gdvj4j2")
+            .await
+            .expect("failed to get scraped comments");
+
+        assert!(comments.len() == 2);
+        assert!(comments[0].id == 1);
+        assert!(comments[0].username.as_deref() == Some("LunarLandr"));
+        assert!(comments[0].score == 2);
+        assert!(comments[0].created.is_some());
+        assert!(comments[0].replies.len() == 1);
+
+        let reply = &comments[0].replies[0];
+        assert!(reply.id == 2);
+        assert!(reply.parent_id == Some(1));
+        assert!(reply.replies.len() == 1);
+        assert!(reply.replies[0].id == 3);
+        assert!(reply.replies[0].username.is_none());
+
+        assert!(comments[1].id == 4);
+        assert!(&*comments[1].body == "orphan");
+    }
+
+    #[test]
+    fn scraped_post_malformed_optional_fields() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [
+                        {
+                            "id": "nw7w6cmlvye",
+                            "description": null,
+                            "link": "https://cdn.imgchest.com/files/nw7w6cmlvye.png",
+                            "position": 1,
+                            "created": 12,
+                            "width": "wide"
+                        }
+                    ],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": {
+                        "username": "LunarLandr"
+                    },
+                    "views": 198,
+                    "privacy": "unknown",
+                    "created": "yesterday",
+                    "score": null,
+                    "tags": "retro"
+                }
+            }
+        });
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"app\" data-page=\"{}\"></div></body></html>",
+            escape_html_attr(&page_data.to_string())
+        );
+        let html = scraper::Html::parse_document(&html);
+        let post = ScrapedPost::from_html(&html).expect("failed to parse scraped post");
+
+        assert!(post.privacy.is_none());
+        assert!(post.created.is_none());
+        assert!(post.score.is_none());
+        assert!(post.tags.is_empty());
+        assert!(post.images[0].created.is_none());
+        assert!(post.images[0].width.is_none());
+        assert!(post.images[0].height.is_none());
+    }
+
+    #[test]
+    fn scraped_post_malformed_comments() {
+        let page_data = serde_json::json!({
+            "props": {
+                "post": {
+                    "files": [],
+                    "nsfw": 0,
+                    "slug": "3qe4gdvj4j2",
+                    "title": "Donkey Kong - Video Game From The Mid 80's",
+                    "user": {
+                        "username": "LunarLandr"
+                    },
+                    "views": 198,
+                    "comments": [
+                        {
+                            "id": "1",
+                            "user": { "username": "LunarLandr" },
+                            "comment": "first",
+                            "score": null,
+                            "replies": [
+                                {
+                                    "id": 2,
+                                    "user": { "username": "other" }
+                                },
+                                {
+                                    "id": 3,
+                                    "user": { "username": "other" },
+                                    "body": "kept reply",
+                                    "score": "bad",
+                                    "replies": null
+                                }
+                            ]
+                        },
+                        {
+                            "user": { "username": "other" },
+                            "comment": "missing id"
+                        },
+                        "not a comment"
+                    ]
+                }
+            }
+        });
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"app\" data-page=\"{}\"></div></body></html>",
+            escape_html_attr(&page_data.to_string())
+        );
+        let html = scraper::Html::parse_document(&html);
+        let post = ScrapedPost::from_html(&html).expect("failed to parse scraped post");
+
+        assert!(post.comments.len() == 1);
+        assert!(post.comments[0].id == 1);
+        assert!(post.comments[0].score == 0);
+        assert!(post.comments[0].replies.len() == 1);
+        assert!(post.comments[0].replies[0].id == 3);
+        assert!(&*post.comments[0].replies[0].body == "kept reply");
+
+        let mut page_data = page_data;
+        page_data["props"]["post"]["comments"] = serde_json::Value::Null;
+        let html = format!(
+            "<!DOCTYPE html><html><body><div id=\"app\" data-page=\"{}\"></div></body></html>",
+            escape_html_attr(&page_data.to_string())
+        );
+        let html = scraper::Html::parse_document(&html);
+        let post = ScrapedPost::from_html(&html).expect("failed to parse scraped post");
+
+        assert!(post.comments.is_empty());
+    }
+
+    #[test]
+    fn post_extra_round_trip() {
+        let mut json = mock_post_json();
+        json["new_post_field"] = serde_json::json!({ "nested": [1, 2, 3] });
+        json["images"][0]["new_file_field"] = serde_json::json!("value");
+
+        let post: Post = serde_json::from_value(json).expect("failed to parse post");
+        assert!(post.extra["new_post_field"] == serde_json::json!({ "nested": [1, 2, 3] }));
+        assert!(post.images[0].extra["new_file_field"] == "value");
+        assert!(!post.extra.contains_key("images"));
+
+        let serialized = serde_json::to_value(&post).expect("failed to serialize post");
+        assert!(serialized["new_post_field"] == serde_json::json!({ "nested": [1, 2, 3] }));
+        assert!(serialized["images"][0]["new_file_field"] == "value");
+
+        let round_tripped: Post =
+            serde_json::from_value(serialized.clone()).expect("failed to parse post");
+        assert!(round_tripped.extra == post.extra);
+        assert!(round_tripped.images[0].extra == post.images[0].extra);
+        assert!(
+            serde_json::to_value(&round_tripped).expect("failed to serialize post") == serialized
+        );
+    }
+
+    #[test]
+    fn user_extra_round_trip() {
+        let json = serde_json::json!({
+            "name": "LunarLandr",
+            "posts": 12,
+            "comments": 3,
+            "created": "2019-11-03T00:36:00.000000Z",
+            "new_user_field": true
+        });
+
+        let user: User = serde_json::from_value(json).expect("failed to parse user");
+        assert!(user.extra["new_user_field"] == true);
+
+        let serialized = serde_json::to_value(&user).expect("failed to serialize user");
+        let round_tripped: User =
+            serde_json::from_value(serialized.clone()).expect("failed to parse user");
+        assert!(round_tripped.extra == user.extra);
+        assert!(
+            serde_json::to_value(&round_tripped).expect("failed to serialize user") == serialized
+        );
+    }
+
+    #[test]
+    fn media_kind_inference() {
+        assert!(
+            MediaKind::from_link("https://cdn.imgchest.com/files/nw7w6cmlvye.png")
+                == MediaKind::Image
+        );
+        assert!(
+            MediaKind::from_link("https://cdn.imgchest.com/files/a.JPEG?x=1#y") == MediaKind::Image
+        );
+        assert!(
+            MediaKind::from_link("https://cdn.imgchest.com/files/a.gif") == MediaKind::AnimatedGif
+        );
+        assert!(MediaKind::from_link("https://cdn.imgchest.com/files/a.mp4") == MediaKind::Video);
+        assert!(MediaKind::from_link("https://cdn.imgchest.com/files/a") == MediaKind::Unknown);
+        assert!(MediaKind::from_link("https://cdn.imgchest.com/files/.png") == MediaKind::Unknown);
+        assert!(MediaKind::from_mime_type("video/webm; codecs=vp9") == MediaKind::Video);
+        assert!(MediaKind::from_mime_type("IMAGE/GIF") == MediaKind::AnimatedGif);
+        assert!(MediaKind::from_mime_type("application/octet-stream") == MediaKind::Unknown);
+
+        let mut json = mock_post_json();
+        let post: Post = serde_json::from_value(json.clone()).expect("failed to parse post");
+        assert!(post.images[0].extension() == Some("png"));
+        assert!(post.images[0].mime_type() == Some("image/png"));
+        assert!(post.images[0].media_kind() == MediaKind::Image);
+
+        json["images"][0]["link"] = "https://cdn.imgchest.com/files/nw7w6cmlvye".into();
+        json["images"][0]["mime_type"] = "video/mp4".into();
+        let post: Post = serde_json::from_value(json).expect("failed to parse post");
+        assert!(post.images[0].extension().is_none());
+        assert!(post.images[0].mime_type() == Some("video/mp4"));
+        assert!(post.images[0].media_kind() == MediaKind::Video);
+    }
+
+    #[tokio::test]
+    async fn mock_upload_from_path_reopens_file() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let temp_path =
+            std::env::temp_dir().join(format!("imgchest-upload-reopen-{}.png", std::process::id()));
+        tokio::fs::write(&temp_path, b"old file")
+            .await
+            .expect("failed to write temp file");
+        let file = UploadPostFile::from_path(&temp_path)
+            .await
+            .expect("failed to open temp file");
+
+        // The file is read again when it is sent.
+        tokio::fs::write(&temp_path, b"new file")
+            .await
+            .expect("failed to write temp file");
+        let mut builder = CreatePostBuilder::new();
+        builder.image(file);
+        client
+            .create_post(builder)
+            .await
+            .expect("failed to create post");
+
+        let requests = server
+            .received_requests()
+            .await
+            .expect("request recording is disabled");
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert!(body.contains("new file"));
+        assert!(!body.contains("old file"));
+        assert!(requests[0].headers.get("content-length").is_some());
+        assert!(requests[0].headers.get("transfer-encoding").is_none());
+
+        // Errors opening the file are returned by the upload.
+        let file = UploadPostFile::from_path(&temp_path)
+            .await
+            .expect("failed to open temp file");
+        tokio::fs::remove_file(&temp_path)
+            .await
+            .expect("failed to remove temp file");
+        let mut builder = CreatePostBuilder::new();
+        builder.image(file);
+        let err = client
+            .create_post(builder)
+            .await
+            .expect_err("upload of a removed file should fail");
+        assert!(matches!(err, Error::Reqwest(_)), "{err:?}");
+
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn mock_upload_progress() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let temp_path = std::env::temp_dir().join(format!(
+            "imgchest-upload-progress-{}.png",
+            std::process::id()
+        ));
+        tokio::fs::write(&temp_path, vec![1; 150_000])
+            .await
+            .expect("failed to write temp file");
+        let path_file = UploadPostFile::from_path(&temp_path)
+            .await
+            .expect("failed to open temp file");
+        assert!(path_file.size() == Some(150_000));
+
+        let client = mock_retry_client(&server, fast_retry_policy());
+        let (sender, receiver) = tokio::sync::watch::channel(UploadProgress::default());
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .image(UploadPostFile::from_bytes("test.png", vec![0; 200_000]))
+            .image(path_file)
+            .progress(sender);
+        client
+            .create_post(builder)
+            .await
+            .expect("failed to create post");
+        tokio::fs::remove_file(&temp_path)
+            .await
+            .expect("failed to remove temp file");
+
+        {
+            let progress = receiver.borrow();
+            assert!(progress.total_bytes == Some(350_000));
+            assert!(progress.bytes_sent == 350_000);
+            assert!(progress.files.len() == 2);
+            assert!(progress.files[0].file_name == "test.png");
+            assert!(progress.files[0].bytes_sent == 200_000);
+            assert!(progress.files[1].bytes_sent == 150_000);
+            assert!(progress.files[1].total_bytes == Some(150_000));
+        }
+
+        let (sender, receiver) = tokio::sync::watch::channel(UploadProgress::default());
+        client
+            .add_post_images_with_progress(
+                "3qe4gdvj4j2",
+                [UploadPostFile::from_bytes("test.png", vec![0; 16])],
+                sender,
+            )
+            .await
+            .expect("failed to add post images");
+
+        let progress = receiver.borrow();
+        assert!(progress.total_bytes == Some(16));
+        assert!(progress.bytes_sent == 16);
+    }
+
+    #[tokio::test]
+    async fn mock_upload_validation() {
+        const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        const GIF_HEADER: &[u8] = b"GIF89a\x01\0\x01\0\0\0\0\0\0\0";
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let png = UploadPostFile::from_bytes("test.png", PNG_HEADER.to_vec());
+        assert!(png.content_type() == Some("image/png"));
+        let gif = UploadPostFile::from_bytes("test.gif", GIF_HEADER.to_vec());
+        assert!(gif.content_type() == Some("image/gif"));
+        let unknown = UploadPostFile::from_bytes("test.bin", vec![0; 16]);
+        assert!(unknown.content_type().is_none());
+
+        let mut upload_limits = UploadLimits::new();
+        upload_limits
+            .max_file_size(32)
+            .max_images(2)
+            .allowed_content_types(["image/png", "image/jpeg"]);
+        let client = Client::builder()
+            .api_base_url(Url::parse(&server.uri()).unwrap())
+            .token("token")
+            .upload_limits(upload_limits)
+            .build()
+            .expect("failed to build client");
+
+        let mut builder = CreatePostBuilder::new();
+        for _ in 0..3 {
+            builder.image(UploadPostFile::from_bytes("test.png", PNG_HEADER.to_vec()));
+        }
+        let err = client
+            .create_post(builder)
+            .await
+            .expect_err("should have had too many images");
+        assert!(matches!(err, Error::TooManyImages { count: 3, max: 2 }));
+
+        let mut data = PNG_HEADER.to_vec();
+        data.resize(64, 0);
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_bytes("large.png", data));
+        let err = client
+            .create_post(builder)
+            .await
+            .expect_err("file should have been too large");
+        assert!(matches!(
+            err,
+            Error::UploadTooLarge { ref file_name, size: 64, max_size: 32 } if &**file_name == "large.png"
+        ));
+
+        let err = client
+            .add_post_images("3qe4gdvj4j2", [png, gif])
+            .await
+            .expect_err("gif should not have been allowed");
+        assert!(matches!(
+            err,
+            Error::UnsupportedUploadFormat { ref file_name, ref content_type }
+                if &**file_name == "test.gif" && content_type.as_deref() == Some("image/gif")
+        ));
+
+        let err = client
+            .add_post_images("3qe4gdvj4j2", [unknown])
+            .await
+            .expect_err("unknown format should not have been allowed");
+        assert!(matches!(
+            err,
+            Error::UnsupportedUploadFormat {
+                content_type: None,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn mock_add_post_images_batched() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut upload_limits = UploadLimits::new();
+        upload_limits.max_images(2);
+        let client = Client::builder()
+            .api_base_url(Url::parse(&server.uri()).unwrap())
+            .token("token")
+            .upload_limits(upload_limits)
+            .build()
+            .expect("failed to build client");
+
+        let images =
+            |n| (0..n).map(|i| UploadPostFile::from_bytes(&format!("{i}.png"), vec![0; 16]));
+        client
+            .add_post_images_batched("3qe4gdvj4j2", images(3))
+            .await
+            .expect("failed to add images");
+
+        let err = client
+            .add_post_images_batched("3qe4gdvj4j2", images(1))
+            .await
+            .expect_err("batch should have failed");
+        match err {
+            Error::BatchUpload {
+                post_id,
+                batch,
+                uploaded,
+                error,
+            } => {
+                assert!(post_id.as_deref() == Some("3qe4gdvj4j2"));
+                assert!(batch == 0);
+                assert!(uploaded == 0);
+                assert!(error.status() == Some(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn mock_create_post_batched_first_batch_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+
+        let mut builder = CreatePostBuilder::new();
+        builder
+            .title("no")
+            .image(UploadPostFile::from_bytes("0.png", vec![0; 16]));
+        let err = client
+            .create_post_batched(builder)
+            .await
+            .expect_err("title should be too short");
+        assert!(matches!(err, Error::TitleTooShort), "{err:?}");
+
+        let mut builder = CreatePostBuilder::new();
+        builder.image(UploadPostFile::from_bytes("0.png", vec![0; 16]));
+        let err = client
+            .create_post_batched(builder)
+            .await
+            .expect_err("first batch should have failed");
+        assert!(!matches!(err, Error::BatchUpload { .. }), "{err:?}");
+        assert!(err.status() == Some(StatusCode::INTERNAL_SERVER_ERROR));
+
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn mock_create_post_batched() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": mock_post_json() })),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut upload_limits = UploadLimits::new();
+        upload_limits.max_images(2);
+        let client = Client::builder()
+            .api_base_url(Url::parse(&server.uri()).unwrap())
+            .token("token")
+            .upload_limits(upload_limits)
+            .build()
+            .expect("failed to build client");
+
+        let mut builder = CreatePostBuilder::new();
+        for i in 0..5 {
+            builder.image(UploadPostFile::from_bytes(&format!("{i}.png"), vec![0; 16]));
+        }
+        let err = client
+            .create_post_batched(builder)
+            .await
+            .expect_err("third batch should have failed");
+        match err {
+            Error::BatchUpload {
+                post_id,
+                batch,
+                uploaded,
+                error,
+            } => {
+                assert!(post_id.as_deref() == Some("3qe4gdvj4j2"));
+                assert!(batch == 2);
+                assert!(uploaded == 4);
+                assert!(error.status() == Some(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+
+        server.verify().await;
+        let requests = server
+            .received_requests()
+            .await
+            .expect("request recording is disabled");
+        let file_names: Vec<Vec<String>> = requests
+            .iter()
+            .map(|request| {
+                let body = String::from_utf8_lossy(&request.body);
+                body.split("filename=\"")
+                    .skip(1)
+                    .filter_map(|part| part.split('"').next())
+                    .map(String::from)
+                    .collect()
+            })
+            .collect();
+        assert!(
+            file_names
+                == [
+                    vec!["0.png".to_string(), "1.png".to_string()],
+                    vec!["2.png".to_string(), "3.png".to_string()],
+                    vec!["4.png".to_string()],
+                ]
+        );
+    }
+
+    #[tokio::test]
+    async fn mock_upload_descriptions() {
+        let mut post_json = mock_post_json();
+        let mut images = Vec::new();
+        for (position, (id, original_name)) in [
+            ("aaaaaaaaaaa", None),
+            ("bbbbbbbbbbb", Some("b.png")),
+            ("ccccccccccc", Some("a.png")),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut image = post_json["images"][0].clone();
+            image["id"] = id.into();
+            image["description"] = serde_json::Value::Null;
+            image["position"] = (position + 1).into();
+            image["original_name"] = original_name.into();
+            images.push(image);
+        }
+        post_json["images"] = images.into();
+        post_json["image_count"] = 3.into();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": post_json })),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        let mut updated_file = post_json["images"][2].clone();
+        updated_file["description"] = "first".into();
+        Mock::given(method("PATCH"))
+            .and(path("/v1/files"))
+            .and(wiremock::matchers::body_json(serde_json::json!({
+                "data": [{ "id": "ccccccccccc", "description": "first" }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "data": [updated_file] })),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/v1/files"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::builder()
+            .api_base_url(Url::parse(&server.uri()).unwrap())
+            .token("token")
+            .build()
+            .expect("failed to build client");
+        let mut first = UploadPostFile::from_bytes("a.png", vec![0; 16]);
+        first.description("first");
+        let second = UploadPostFile::from_bytes("b.png", vec![0; 16]);
+        let post = client
+            .add_post_images("3qe4gdvj4j2", [first, second])
+            .await
+            .expect("failed to add post images");
+        assert!(post.images[2].description.as_deref() == Some("first"));
+        assert!(post.images[1].description.is_none());
+
+        let mut file = UploadPostFile::from_bytes("b.png", vec![0; 16]);
+        file.description("second");
+        let err = client
+            .add_post_images("3qe4gdvj4j2", [file])
+            .await
+            .expect_err("setting descriptions should have failed");
+        match err {
+            Error::FileDescriptions { post, error } => {
+                assert!(&*post.id == "3qe4gdvj4j2");
+                assert!(error.status() == Some(StatusCode::INTERNAL_SERVER_ERROR));
+            }
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_upload_descriptions_reordered() {
+        let mut post_json = mock_post_json();
+        let mut images = Vec::new();
+        for (position, (id, original_name)) in [
+            ("aaaaaaaaaaa", Some("b.png")),
+            ("bbbbbbbbbbb", Some("a.png")),
+            ("ccccccccccc", None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut image = post_json["images"][0].clone();
+            image["id"] = id.into();
+            image["description"] = serde_json::Value::Null;
+            image["position"] = (position + 1).into();
+            image["original_name"] = original_name.into();
+            images.push(image);
+        }
+        post_json["images"] = images.into();
+        post_json["image_count"] = 3.into();
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/post/3qe4gdvj4j2/add"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "data": post_json })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/v1/files"))
+            .and(wiremock::matchers::body_json(serde_json::json!({
+                "data": [
+                    { "id": "bbbbbbbbbbb", "description": "first" },
+                    { "id": "ccccccccccc", "description": "second" },
+                    { "id": "aaaaaaaaaaa", "description": "third" },
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": post_json["images"]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client.set_token("token");
+        let files = [("a.png", "first"), ("a.png", "second"), ("b.png", "third")].map(
+            |(file_name, description)| {
+                let mut file = UploadPostFile::from_bytes(file_name, vec![0; 16]);
+                file.description(description);
+                file
+            },
+        );
+        client
+            .add_post_images("3qe4gdvj4j2", files)
+            .await
+            .expect("failed to add post images");
+    }
 }
@@ -1,13 +1,18 @@
 mod list_posts;
+mod media_kind;
 mod post;
 mod scraped_post;
 mod scraped_user;
 mod user;
 
+use std::collections::HashMap;
+
 pub use self::list_posts::ListPostsPost;
+pub use self::media_kind::MediaKind;
 pub use self::post::File as PostFile;
 pub use self::post::Post;
 pub use self::post::Privacy as PostPrivacy;
+pub use self::scraped_post::Comment as ScrapedComment;
 pub use self::scraped_post::File as ScrapedPostFile;
 pub use self::scraped_post::FromHtmlError as InvalidScrapedPostError;
 pub use self::scraped_post::ScrapedPost;
@@ -52,3 +57,13 @@ pub(crate) struct ApiCompletedResponse {
     /// The operation message response.
     pub message: Option<Box<str>>,
 }
+
+/// The response for when the api returns an error
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ApiErrorResponse {
+    /// The error message.
+    pub message: Option<Box<str>>,
+
+    /// Validation errors, keyed by field name.
+    pub errors: Option<HashMap<Box<str>, Vec<Box<str>>>>,
+}
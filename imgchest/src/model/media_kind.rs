@@ -0,0 +1,104 @@
+/// The kind of media a file contains
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum MediaKind {
+    /// A still image
+    #[serde(rename = "image")]
+    Image,
+
+    /// A gif.
+    ///
+    /// Gifs are assumed to be animated, as the extension alone cannot tell.
+    #[serde(rename = "animated_gif")]
+    AnimatedGif,
+
+    /// A video
+    #[serde(rename = "video")]
+    Video,
+
+    /// The media kind could not be determined
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl MediaKind {
+    /// Infer the media kind from a file extension, like "png".
+    ///
+    /// The extension is matched case-insensitively.
+    pub fn from_extension(extension: &str) -> Self {
+        let extension = extension.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Self::AnimatedGif,
+            "mp4" | "m4v" | "webm" | "mov" | "mkv" | "avi" => Self::Video,
+            "png" | "jpg" | "jpeg" | "webp" | "avif" | "bmp" | "tif" | "tiff" | "heic" => {
+                Self::Image
+            }
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Infer the media kind from a MIME type, like "image/png".
+    pub fn from_mime_type(mime_type: &str) -> Self {
+        let essence = mime_type
+            .split(';')
+            .next()
+            .unwrap_or(mime_type)
+            .trim()
+            .to_ascii_lowercase();
+        match essence.split_once('/') {
+            Some(("image", "gif")) => Self::AnimatedGif,
+            Some(("image", _)) => Self::Image,
+            Some(("video", _)) => Self::Video,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Infer the media kind from a link to a file.
+    pub fn from_link(link: &str) -> Self {
+        link_extension(link)
+            .map(Self::from_extension)
+            .unwrap_or(Self::Unknown)
+    }
+
+    /// Get this as a str.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::AnimatedGif => "animated_gif",
+            Self::Video => "video",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Get the MIME type for a file extension, if it is known.
+pub(crate) fn extension_mime_type(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_ascii_lowercase();
+    let mime_type = match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        "heic" => "image/heic",
+        "gif" => "image/gif",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+/// Get the extension of the file name of a link, ignoring any query or fragment.
+pub(crate) fn link_extension(link: &str) -> Option<&str> {
+    let path = link.split(['?', '#']).next()?;
+    let file_name = path.rsplit('/').next()?;
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    if stem.is_empty() || extension.is_empty() {
+        return None;
+    }
+    Some(extension)
+}
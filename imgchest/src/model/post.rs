@@ -1,3 +1,7 @@
+use super::media_kind::extension_mime_type;
+use super::media_kind::link_extension;
+use crate::MediaKind;
+use std::collections::HashMap;
 use std::num::NonZeroU32;
 use time::OffsetDateTime;
 
@@ -40,8 +44,10 @@ pub struct Post {
     ///
     /// Only present if the current user owns this post.
     pub delete_url: Option<Box<str>>,
-    // #[serde(flatten)]
-    // extra: std::collections::HashMap<Box<str>, serde_json::Value>,
+
+    /// Extra key values
+    #[serde(flatten)]
+    pub extra: HashMap<Box<str>, serde_json::Value>,
 }
 
 /// An API file of a post
@@ -69,8 +75,36 @@ pub struct File {
     ///
     /// Only present if the current user owns this image.
     pub original_name: Option<Box<str>>,
-    // #[serde(flatten)]
-    // extra: std::collections::HashMap<Box<str>, serde_json::Value>,
+
+    /// Extra key values
+    #[serde(flatten)]
+    pub extra: HashMap<Box<str>, serde_json::Value>,
+}
+
+impl File {
+    /// Get the extension of this file, from its link.
+    pub fn extension(&self) -> Option<&str> {
+        link_extension(&self.link)
+    }
+
+    /// Get the MIME type of this file.
+    ///
+    /// This uses the type reported by the server if present,
+    /// falling back to guessing from the extension.
+    pub fn mime_type(&self) -> Option<&str> {
+        ["mime_type", "mime"]
+            .iter()
+            .find_map(|key| self.extra.get(*key)?.as_str())
+            .or_else(|| self.extension().and_then(extension_mime_type))
+    }
+
+    /// Get the kind of media this file contains.
+    pub fn media_kind(&self) -> MediaKind {
+        self.mime_type()
+            .map(MediaKind::from_mime_type)
+            .filter(|kind| *kind != MediaKind::Unknown)
+            .unwrap_or_else(|| MediaKind::from_link(&self.link))
+    }
 }
 
 /// The post privacy
@@ -1,6 +1,13 @@
+use super::media_kind::extension_mime_type;
+use super::media_kind::link_extension;
+use crate::MediaKind;
+use crate::PostPrivacy;
 use once_cell::sync::Lazy;
 use scraper::Html;
 use scraper::Selector;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use time::OffsetDateTime;
 
 static APP_SELECTOR: Lazy<Selector> = Lazy::new(|| Selector::parse("#app").unwrap());
 
@@ -29,11 +36,14 @@ pub struct ScrapedPost {
     /// The author of the post
     pub username: Box<str>,
 
-    // /// The post privacy
-    // pub privacy: String,
+    /// The post privacy
+    #[serde(default)]
+    pub privacy: Option<PostPrivacy>,
+
+    /// ?
+    #[serde(default)]
+    pub report_status: Option<i32>,
 
-    // /// ?
-    // pub report_status: u32,
     /// The number of views
     pub views: u64,
 
@@ -43,10 +53,34 @@ pub struct ScrapedPost {
     /// The number of images
     pub image_count: u64,
 
-    // /// The timestamp of post creation
-    // pub created: String,
+    /// The time this was created
+    #[serde(default, with = "time::serde::iso8601::option")]
+    pub created: Option<OffsetDateTime>,
+
+    /// The score of the post
+    #[serde(default)]
+    pub score: Option<i64>,
+
+    /// The number of times this post was favorited
+    #[serde(default)]
+    pub favorites: Option<u64>,
+
+    /// The post description
+    #[serde(default)]
+    pub description: Option<Box<str>>,
+
+    /// The post tags
+    #[serde(default)]
+    pub tags: Box<[Box<str>]>,
+
     /// Post images
     pub images: Box<[File]>,
+
+    /// Post comments.
+    ///
+    /// These are threaded, so only top-level comments are present here.
+    #[serde(default)]
+    pub comments: Box<[Comment]>,
 }
 
 impl ScrapedPost {
@@ -64,11 +98,11 @@ impl ScrapedPost {
         let page_data: PageData =
             serde_json::from_str(data_page_attr).map_err(FromHtmlError::InvalidDataPage)?;
 
+        let post = page_data.props.post;
+
         // Overflowing a u64 with image entries is impossible.
-        let image_count = u64::try_from(page_data.props.post.files.len()).unwrap();
-        let images: Vec<_> = page_data
-            .props
-            .post
+        let image_count = u64::try_from(post.files.len()).unwrap();
+        let images: Vec<_> = post
             .files
             .into_iter()
             .map(|file| File {
@@ -76,20 +110,119 @@ impl ScrapedPost {
                 description: file.description,
                 link: file.link,
                 position: file.position,
+                created: file.created,
+                width: file.width,
+                height: file.height,
+                mime_type: file.mime_type,
             })
             .collect();
+
+        let tags: Vec<_> = post
+            .tags
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tag| match tag {
+                PageDataTag::Name(name) => name,
+                PageDataTag::Object { name } => name,
+            })
+            .collect();
+
+        let mut comments = Vec::new();
+        for comment in post.comments.unwrap_or_default() {
+            flatten_comment(comment, None, &mut comments);
+        }
+        let comments = thread_comments(comments);
+
         Ok(Self {
-            id: page_data.props.post.slug,
-            title: page_data.props.post.title,
-            username: page_data.props.post.user.username,
-            views: page_data.props.post.views,
-            nsfw: page_data.props.post.nsfw != 0,
+            id: post.slug,
+            title: post.title,
+            username: post.user.username,
+            privacy: post.privacy,
+            report_status: post.report_status,
+            views: post.views,
+            nsfw: post.nsfw != 0,
             image_count,
+            created: post.created,
+            score: post.score,
+            favorites: post.favorites,
+            description: post.description,
+            tags: tags.into(),
             images: images.into(),
+            comments: comments.into(),
         })
     }
 }
 
+/// Flatten a comment and its nested replies.
+///
+/// Malformed comments are skipped, along with their replies.
+fn flatten_comment(comment: serde_json::Value, parent_id: Option<u64>, out: &mut Vec<Comment>) {
+    let Ok(comment) = serde_json::from_value::<PageDataComment>(comment) else {
+        return;
+    };
+    let id = comment.id;
+    out.push(Comment {
+        id,
+        username: comment.user.map(|user| user.username),
+        body: comment.comment,
+        score: comment.score.unwrap_or(0),
+        created: comment.created_at,
+        parent_id: comment.parent_id.or(parent_id),
+        replies: Vec::new(),
+    });
+
+    for reply in comment.replies.unwrap_or_default() {
+        flatten_comment(reply, Some(id), out);
+    }
+}
+
+/// Rebuild the reply tree from a flat list of comments.
+///
+/// Comments whose parent cannot be found are treated as top-level comments.
+fn thread_comments(comments: Vec<Comment>) -> Vec<Comment> {
+    fn attach(comment: &mut Comment, children: &mut HashMap<u64, Vec<Comment>>) {
+        if let Some(mut replies) = children.remove(&comment.id) {
+            for reply in replies.iter_mut() {
+                attach(reply, children);
+            }
+            comment.replies = replies;
+        }
+    }
+
+    let ids: HashSet<u64> = comments.iter().map(|comment| comment.id).collect();
+    let mut children: HashMap<u64, Vec<Comment>> = HashMap::new();
+    let mut roots = Vec::new();
+    for comment in comments {
+        match comment
+            .parent_id
+            .filter(|parent_id| *parent_id != comment.id && ids.contains(parent_id))
+        {
+            Some(parent_id) => children.entry(parent_id).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+
+    for root in roots.iter_mut() {
+        attach(root, &mut children);
+    }
+
+    // Anything left over is part of a reply cycle.
+    // Promote it to the top level instead of dropping it.
+    let mut leftover_ids: Vec<u64> = children.keys().copied().collect();
+    leftover_ids.sort_unstable();
+    for parent_id in leftover_ids {
+        let Some(comments) = children.remove(&parent_id) else {
+            continue;
+        };
+        for mut comment in comments {
+            attach(&mut comment, &mut children);
+            roots.push(comment);
+        }
+    }
+
+    roots
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct PageData {
     props: PageDataProps,
@@ -108,6 +241,41 @@ struct PageDataPost {
     title: Box<str>,
     user: PageDataUser,
     views: u64,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize")]
+    privacy: Option<PostPrivacy>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize_int")]
+    report_status: Option<i32>,
+    #[serde(
+        default,
+        alias = "created_at",
+        deserialize_with = "crate::serde::lenient::deserialize_iso8601"
+    )]
+    created: Option<OffsetDateTime>,
+    #[serde(
+        default,
+        alias = "votes",
+        deserialize_with = "crate::serde::lenient::deserialize_int"
+    )]
+    score: Option<i64>,
+    #[serde(
+        default,
+        alias = "favorites_count",
+        deserialize_with = "crate::serde::lenient::deserialize_int"
+    )]
+    favorites: Option<u64>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize")]
+    description: Option<Box<str>>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize")]
+    tags: Option<Vec<PageDataTag>>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize")]
+    comments: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum PageDataTag {
+    Name(Box<str>),
+    Object { name: Box<str> },
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -115,12 +283,53 @@ struct PageDataUser {
     username: Box<str>,
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct PageDataComment {
+    #[serde(deserialize_with = "crate::serde::int_or_str::deserialize")]
+    id: u64,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize")]
+    user: Option<PageDataUser>,
+    #[serde(alias = "body")]
+    comment: Box<str>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize_int")]
+    score: Option<i64>,
+    #[serde(
+        default,
+        deserialize_with = "crate::serde::lenient::deserialize_iso8601"
+    )]
+    created_at: Option<OffsetDateTime>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize_int")]
+    parent_id: Option<u64>,
+    #[serde(
+        default,
+        alias = "children",
+        deserialize_with = "crate::serde::lenient::deserialize"
+    )]
+    replies: Option<Vec<serde_json::Value>>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct PageDataFile {
     id: Box<str>,
     description: Option<Box<str>>,
     link: Box<str>,
     position: u32,
+    #[serde(
+        default,
+        alias = "created_at",
+        deserialize_with = "crate::serde::lenient::deserialize_iso8601"
+    )]
+    created: Option<OffsetDateTime>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize_int")]
+    width: Option<u32>,
+    #[serde(default, deserialize_with = "crate::serde::lenient::deserialize_int")]
+    height: Option<u32>,
+    #[serde(
+        default,
+        alias = "mime",
+        deserialize_with = "crate::serde::lenient::deserialize"
+    )]
+    mime_type: Option<Box<str>>,
 }
 
 /// A post file
@@ -139,6 +348,73 @@ pub struct File {
     ///
     /// Starts at 1.
     pub position: u32,
-    // /// The file creation time
-    // pub created: u32,
+
+    /// The time this file was created
+    #[serde(default, with = "time::serde::iso8601::option")]
+    pub created: Option<OffsetDateTime>,
+
+    /// The width of the file, in pixels
+    #[serde(default)]
+    pub width: Option<u32>,
+
+    /// The height of the file, in pixels
+    #[serde(default)]
+    pub height: Option<u32>,
+
+    /// The MIME type of the file, if reported by the server
+    #[serde(default)]
+    pub mime_type: Option<Box<str>>,
+}
+
+impl File {
+    /// Get the extension of this file, from its link.
+    pub fn extension(&self) -> Option<&str> {
+        link_extension(&self.link)
+    }
+
+    /// Get the MIME type of this file.
+    ///
+    /// This uses the type reported by the server if present,
+    /// falling back to guessing from the extension.
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type
+            .as_deref()
+            .or_else(|| self.extension().and_then(extension_mime_type))
+    }
+
+    /// Get the kind of media this file contains.
+    pub fn media_kind(&self) -> MediaKind {
+        self.mime_type()
+            .map(MediaKind::from_mime_type)
+            .filter(|kind| *kind != MediaKind::Unknown)
+            .unwrap_or_else(|| MediaKind::from_link(&self.link))
+    }
+}
+
+/// A post comment
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Comment {
+    /// The comment id
+    pub id: u64,
+
+    /// The author of the comment.
+    ///
+    /// This is `None` if the author's account no longer exists.
+    pub username: Option<Box<str>>,
+
+    /// The comment text
+    pub body: Box<str>,
+
+    /// The comment score
+    pub score: i64,
+
+    /// The time this comment was created
+    #[serde(with = "time::serde::iso8601::option")]
+    pub created: Option<OffsetDateTime>,
+
+    /// The id of the comment this is replying to
+    pub parent_id: Option<u64>,
+
+    /// Replies to this comment
+    pub replies: Vec<Comment>,
 }
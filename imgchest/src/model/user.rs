@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use time::OffsetDateTime;
 
 /// The user
@@ -15,6 +16,8 @@ pub struct User {
     /// The time this user was created
     #[serde(with = "time::serde::iso8601")]
     pub created: OffsetDateTime,
-    //#[serde(flatten)]
-    //extra: std::collections::HashMap<Box<str>, serde_json::Value>,
+
+    /// Extra key values
+    #[serde(flatten)]
+    pub extra: HashMap<Box<str>, serde_json::Value>,
 }
@@ -49,3 +49,122 @@ pub(crate) mod from_str_to_str {
         serializer.serialize_str(&value)
     }
 }
+
+pub(crate) mod int_or_str {
+    use serde::de::Visitor;
+    use std::marker::PhantomData;
+
+    struct IntOrStrVisitor<T>(PhantomData<T>);
+
+    impl<T> Visitor<'_> for IntOrStrVisitor<T>
+    where
+        T: std::str::FromStr,
+        <T as std::str::FromStr>::Err: std::fmt::Display,
+        T: TryFrom<u64>,
+        <T as TryFrom<u64>>::Error: std::fmt::Display,
+        T: TryFrom<i64>,
+        <T as TryFrom<i64>>::Error: std::fmt::Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(formatter, "a stringified integer or an integer")
+        }
+
+        fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            s.parse().map_err(E::custom)
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            value.try_into().map_err(E::custom)
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            value.try_into().map_err(E::custom)
+        }
+    }
+
+    pub(crate) fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        T: std::str::FromStr,
+        <T as std::str::FromStr>::Err: std::fmt::Display,
+        T: TryFrom<u64>,
+        <T as TryFrom<u64>>::Error: std::fmt::Display,
+        T: TryFrom<i64>,
+        <T as TryFrom<i64>>::Error: std::fmt::Display,
+    {
+        deserializer.deserialize_any(IntOrStrVisitor(PhantomData))
+    }
+
+    /*
+    pub(crate) fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+        T: itoa::Integer,
+    {
+        serializer.serialize_str(itoa::Buffer::new().format(*value))
+    }
+    */
+}
+
+/// Deserializers that map missing or malformed values to `None` instead of failing.
+pub(crate) mod lenient {
+    use serde::de::DeserializeOwned;
+    use serde_json::Value;
+    use time::format_description::well_known::Iso8601;
+    use time::OffsetDateTime;
+
+    pub(crate) fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        T: DeserializeOwned,
+    {
+        let value: Value = serde::Deserialize::deserialize(deserializer)?;
+        Ok(T::deserialize(value).ok())
+    }
+
+    pub(crate) fn deserialize_int<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+        T: TryFrom<i64> + TryFrom<u64>,
+    {
+        let value: Value = serde::Deserialize::deserialize(deserializer)?;
+        let value = match value {
+            Value::Number(value) => match value.as_u64() {
+                Some(value) => T::try_from(value).ok(),
+                None => value.as_i64().and_then(|value| T::try_from(value).ok()),
+            },
+            Value::String(value) => value
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .and_then(|value| T::try_from(value).ok()),
+            _ => None,
+        };
+        Ok(value)
+    }
+
+    pub(crate) fn deserialize_iso8601<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<OffsetDateTime>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value: Value = serde::Deserialize::deserialize(deserializer)?;
+        let value = match value {
+            Value::String(value) => OffsetDateTime::parse(&value, &Iso8601::DEFAULT).ok(),
+            _ => None,
+        };
+        Ok(value)
+    }
+}